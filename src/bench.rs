@@ -0,0 +1,69 @@
+use crate::bitboard::BitBoardState;
+use crate::history::GameHistory;
+use crate::search::SearchDriver;
+use crate::time_manager::SearchLimits;
+use std::time::Instant;
+
+pub const BENCH_DEPTH: usize = 6;
+
+// Openings, middlegames and endgames, the perft positions among them
+const BENCH_POSITIONS: &[&str] = &[
+    "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+    "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+    "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+    "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+    "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+    "r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4",
+    "r2q1rk1/pp2bppp/2n1pn2/3p4/2PP4/2N1PN2/PP2BPPP/R2Q1RK1 b - - 0 9",
+    "2r3k1/pp3ppp/4p3/3pP3/3P4/P4N2/1P3PPP/2R3K1 w - - 0 25",
+    "8/8/4k3/3p4/3P4/4K3/8/8 w - - 0 1",
+    "6k1/5pp1/7p/8/8/6P1/5PKP/3R4 w - - 0 40",
+    "8/5pk1/6p1/8/3Q4/6P1/5PK1/1q6 b - - 0 50",
+];
+
+/// Searches every bench position to `depth` with a fresh transposition table and prints the
+/// nodes of each. The total is deterministic, so it tells whether a change alters the search,
+/// and how much a change to the move ordering or pruning saves.
+pub fn bench(depth: usize) -> u64 {
+    let limits = SearchLimits {
+        depth: Some(depth),
+        ..SearchLimits::default()
+    };
+    let start = Instant::now();
+    let mut total = 0;
+
+    for (i, fen) in BENCH_POSITIONS.iter().enumerate() {
+        let board = BitBoardState::from_fen_strict(fen).unwrap();
+        let mut search_driver = SearchDriver::new(16);
+        search_driver.best_move(&board, &GameHistory::new(), &limits);
+
+        println!(
+            "Position {:>2}: {:>10} nodes  {}",
+            i + 1,
+            search_driver.nodes(),
+            fen
+        );
+        total += search_driver.nodes();
+    }
+
+    let millis = start.elapsed().as_millis() as u64;
+    println!("===========================");
+    println!("Total time (ms) : {}", millis);
+    println!("Nodes searched  : {}", total);
+    println!("Nodes/second    : {}", total * 1000 / millis.max(1));
+    total
+}
+
+#[cfg(test)]
+mod test {
+    use crate::bench::BENCH_POSITIONS;
+    use crate::bitboard::BitBoardState;
+
+    #[test]
+    fn test_bench_positions() {
+        for fen in BENCH_POSITIONS {
+            assert!(BitBoardState::from_fen_strict(fen).is_ok(), "{}", fen);
+        }
+    }
+}
@@ -21,6 +21,15 @@ pub enum Color {
     Black = 1,
 }
 
+impl Color {
+    pub const fn opposite(self) -> Color {
+        match self {
+            Color::White => Color::Black,
+            Color::Black => Color::White,
+        }
+    }
+}
+
 impl TryFrom<usize> for Color {
     type Error = String;
 
@@ -187,8 +196,7 @@ impl Board {
         let en_passant_str = fen_board.next().unwrap_or("-");
         let en_passant = algebraic_to_index(en_passant_str.as_bytes())
             .ok()
-            .map(|v| NonZeroU8::new(v as u8))
-            .flatten();
+            .and_then(|v| NonZeroU8::new(v as u8));
 
         let half_moves = fen_board.next().unwrap_or("0").parse::<usize>().unwrap();
         let full_moves = fen_board.next().unwrap_or("1").parse::<usize>().unwrap();
@@ -216,7 +224,7 @@ impl Board {
         });
 
         self.half_moves += 1;
-        if let Some(_) = self.pieces[m.end_index as usize] {
+        if self.pieces[m.end_index as usize].is_some() {
             self.half_moves = 0;
         }
 
@@ -1,4 +1,4 @@
-use crate::bitboard::{BitBoard, generate_moves, BitBoardState};
+use crate::bitboard::{generate_moves, BitBoard, BitBoardState};
 use crate::board::{Board, Color, Piece};
 
 const CENTER4: u64 = 0x0000001818000000;
@@ -6,13 +6,11 @@ const CENTER16: u64 = 0x00003C3C3C3C0000;
 
 fn evaluate_board(board: &Board, evaluate_color: Color) -> i64 {
     let mut value = 0;
-    for location in board.pieces.iter() {
-        if let Some((piece_color, piece_type)) = location {
-            if evaluate_color == *piece_color {
-                value += piece_value(*piece_type);
-            } else {
-                value -= piece_value(*piece_type);
-            }
+    for (piece_color, piece_type) in board.pieces.iter().flatten() {
+        if evaluate_color == *piece_color {
+            value += piece_value(*piece_type);
+        } else {
+            value -= piece_value(*piece_type);
         }
     }
     value
@@ -26,38 +24,74 @@ pub fn evaluate_bitboard(bitboard: &BitBoardState, evaluate_color: Color) -> i64
     };
 
     let mut value = 0;
-    value += bitboard.bitboard.get_set(evaluate_color, Piece::Pawn).count_ones() as i64;
-    value += bitboard.bitboard.get_set(evaluate_color, Piece::Rook).count_ones() as i64
+    value += bitboard
+        .bitboard
+        .get_set(evaluate_color, Piece::Pawn)
+        .count_ones() as i64;
+    value += bitboard
+        .bitboard
+        .get_set(evaluate_color, Piece::Rook)
+        .count_ones() as i64
         * piece_value(Piece::Rook);
-    value += bitboard.bitboard.get_set(evaluate_color, Piece::Bishop).count_ones() as i64
+    value += bitboard
+        .bitboard
+        .get_set(evaluate_color, Piece::Bishop)
+        .count_ones() as i64
         * piece_value(Piece::Bishop);
-    value += bitboard.bitboard.get_set(evaluate_color, Piece::Knight).count_ones() as i64
+    value += bitboard
+        .bitboard
+        .get_set(evaluate_color, Piece::Knight)
+        .count_ones() as i64
         * piece_value(Piece::Knight);
-    value += bitboard.bitboard.get_set(evaluate_color, Piece::Queen).count_ones() as i64
+    value += bitboard
+        .bitboard
+        .get_set(evaluate_color, Piece::Queen)
+        .count_ones() as i64
         * piece_value(Piece::Queen);
 
-    value += (bitboard.bitboard.get_set(evaluate_color, Piece::Pawn) & CENTER4).count_ones() as i64 * 100;
-    value += (bitboard.bitboard.get_set(evaluate_color, Piece::Pawn) & CENTER16).count_ones() as i64 * 100;
+    value += (bitboard.bitboard.get_set(evaluate_color, Piece::Pawn) & CENTER4).count_ones() as i64
+        * 100;
+    value += (bitboard.bitboard.get_set(evaluate_color, Piece::Pawn) & CENTER16).count_ones()
+        as i64
+        * 100;
 
-    value -= bitboard.bitboard.get_set(opposite_color, Piece::Pawn).count_ones() as i64;
-    value -= bitboard.bitboard.get_set(opposite_color, Piece::Rook).count_ones() as i64
+    value -= bitboard
+        .bitboard
+        .get_set(opposite_color, Piece::Pawn)
+        .count_ones() as i64;
+    value -= bitboard
+        .bitboard
+        .get_set(opposite_color, Piece::Rook)
+        .count_ones() as i64
         * piece_value(Piece::Rook);
-    value -= bitboard.bitboard.get_set(opposite_color, Piece::Bishop).count_ones() as i64
+    value -= bitboard
+        .bitboard
+        .get_set(opposite_color, Piece::Bishop)
+        .count_ones() as i64
         * piece_value(Piece::Bishop);
-    value -= bitboard.bitboard.get_set(opposite_color, Piece::Knight).count_ones() as i64
+    value -= bitboard
+        .bitboard
+        .get_set(opposite_color, Piece::Knight)
+        .count_ones() as i64
         * piece_value(Piece::Knight);
-    value -= bitboard.bitboard.get_set(opposite_color, Piece::Queen).count_ones() as i64
+    value -= bitboard
+        .bitboard
+        .get_set(opposite_color, Piece::Queen)
+        .count_ones() as i64
         * piece_value(Piece::Queen);
 
-    value -= (bitboard.bitboard.get_set(opposite_color, Piece::Pawn) & CENTER4).count_ones() as i64 * 100;
-    value -= (bitboard.bitboard.get_set(opposite_color, Piece::Pawn) & CENTER16).count_ones() as i64 * 100;
+    value -= (bitboard.bitboard.get_set(opposite_color, Piece::Pawn) & CENTER4).count_ones() as i64
+        * 100;
+    value -= (bitboard.bitboard.get_set(opposite_color, Piece::Pawn) & CENTER16).count_ones()
+        as i64
+        * 100;
 
     bitboard.active_color = evaluate_color;
     let our_moves = generate_moves(&bitboard).len();
     bitboard.active_color = opposite_color;
     let their_moves = generate_moves(&bitboard).len();
 
-    let move_val = 2 * (our_moves - their_moves) as i64;
+    let move_val = 2 * (our_moves as i64 - their_moves as i64);
     value += move_val;
 
     value
@@ -73,4 +107,3 @@ fn piece_value(piece: Piece) -> i64 {
         Piece::Pawn => 100,
     }
 }
-
@@ -0,0 +1,105 @@
+use crate::bitboard::BitBoardState;
+
+/// The hashes of the positions that came before the current one, oldest first
+#[derive(Clone, Debug, Default)]
+pub struct GameHistory {
+    hashes: Vec<u64>,
+}
+
+impl GameHistory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.hashes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hashes.is_empty()
+    }
+
+    /// Remembers the position `state` before a move is made on it
+    pub fn push(&mut self, state: &BitBoardState) {
+        self.hashes.push(state.hash);
+    }
+
+    /// Forgets the last position, when the move made on it is taken back
+    pub fn pop(&mut self) {
+        self.hashes.pop();
+    }
+
+    pub fn clear(&mut self) {
+        self.hashes.clear();
+    }
+
+    /// How often `state` occurred before. Only positions with the same side to move since the
+    /// last capture or pawn move can be the same.
+    pub fn repetitions(&self, state: &BitBoardState) -> usize {
+        let reversible = (state.half_moves as usize).min(self.hashes.len());
+
+        self.hashes[self.hashes.len() - reversible..]
+            .iter()
+            .rev()
+            .skip(1)
+            .step_by(2)
+            .filter(|&&hash| hash == state.hash)
+            .count()
+    }
+
+    /// Whether `state` occurred before. The search scores this as a draw, as the side that
+    /// allowed it could repeat the position a third time.
+    pub fn is_repetition(&self, state: &BitBoardState) -> bool {
+        self.repetitions(state) >= 1
+    }
+
+    pub fn is_threefold_repetition(&self, state: &BitBoardState) -> bool {
+        self.repetitions(state) >= 2
+    }
+
+    /// Whether `state` occurred for the fifth time, which ends the game without a claim
+    pub fn is_fivefold_repetition(&self, state: &BitBoardState) -> bool {
+        self.repetitions(state) >= 4
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::bitboard::BitBoardState;
+    use crate::history::GameHistory;
+
+    #[test]
+    fn test_repetition() {
+        let mut board = BitBoardState::new();
+        let mut history = GameHistory::new();
+
+        for _ in 0..2 {
+            for m in &["g1f3", "g8f6", "f3g1", "f6g8"] {
+                assert!(!history.is_threefold_repetition(&board));
+                let m = board.parse_uci_move(m).unwrap();
+                history.push(&board);
+                board.make_move(&m);
+            }
+            assert!(history.is_repetition(&board));
+        }
+        assert!(history.is_threefold_repetition(&board));
+        assert_eq!(history.repetitions(&board), 2);
+
+        for _ in 0..2 {
+            assert!(!history.is_fivefold_repetition(&board));
+            for m in &["g1f3", "g8f6", "f3g1", "f6g8"] {
+                let m = board.parse_uci_move(m).unwrap();
+                history.push(&board);
+                board.make_move(&m);
+            }
+        }
+        assert!(history.is_fivefold_repetition(&board));
+
+        // A pawn move makes the earlier positions unreachable
+        let m = board.parse_uci_move("e2e4").unwrap();
+        history.push(&board);
+        board.make_move(&m);
+        assert_eq!(board.half_moves, 0);
+        assert_eq!(history.repetitions(&board), 0);
+    }
+}
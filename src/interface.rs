@@ -21,7 +21,7 @@ pub fn print_board(board: &Board) {
                 None => print!("   "),
             }
         }
-        print!("\x1B[0m\n");
+        println!("\x1B[0m");
     }
     print!("  ");
     for i in 0..8 {
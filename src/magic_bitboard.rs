@@ -3,7 +3,7 @@
 
 use crate::util::Rng;
 
-const ROOK_MASK: [u64; 64] = {
+pub(crate) const ROOK_MASK: [u64; 64] = {
     let mut masks = [0; 64];
     let mut i = 0;
     while i < 64 {
@@ -13,7 +13,7 @@ const ROOK_MASK: [u64; 64] = {
     masks
 };
 
-const BISHOP_MASK: [u64; 64] = {
+pub(crate) const BISHOP_MASK: [u64; 64] = {
     let mut masks = [0; 64];
     let mut i = 0;
     while i < 64 {
@@ -43,28 +43,41 @@ const BISHOP_ATTACKS: [u64; 64] = {
     attacks
 };
 
-const MAGIC_ATTACK_TABLE: [u64; 100000] = {
-    let a = [0; 100000];
-    a
-};
-
-const MAGIC_BISHOP_TABLE: [MagicRecord; 64] = {
-    [MagicRecord {
-        index: 0,
-        mask: 0,
-        magic: 0,
-        shift: 0,
-    }; 64]
-};
-
-const MAGIC_ROOK_TABLE: [MagicRecord; 64] = {
-    [MagicRecord {
-        index: 0,
-        mask: 0,
-        magic: 0,
-        shift: 0,
-    }; 64]
-};
+// Seeds of the generator for every square whose first candidate is already a magic. Searching
+// from scratch takes far too long for constant evaluation, find_magic still checks them. When
+// the generator or the masks change, `chess-ai seeds` prints new ones.
+const ROOK_SEEDS: [u64; 64] = [
+    607003, 106958, 352268, 945567, 721944, 48732, 2623, 543544, 37621, 37538, 218068, 39689,
+    85191, 369046, 35163, 210251, 59820, 21248, 10929, 94479, 397097, 192382, 56940, 68123, 37621,
+    53773, 5207, 45402, 158880, 680260, 113504, 138051, 71172, 24360, 31237, 73406, 392457, 205389,
+    237127, 431652, 53624, 50126, 154230, 72906, 280165, 184241, 20170, 40260, 28129, 69336, 4106,
+    181393, 105078, 82736, 96339, 21014, 184649, 16907, 75022, 153638, 1277186, 630740, 25709,
+    47033,
+];
+
+const BISHOP_SEEDS: [u64; 64] = [
+    11907, 12031, 2596, 975, 2052, 3816, 2251, 7825, 1466, 3026, 132, 2272, 1351, 923, 544, 295,
+    4570, 1397, 832, 18585, 53919, 7395, 832, 2217, 5264, 2503, 15341, 157430, 91363, 38589, 289,
+    1936, 1110, 334, 12212, 578393, 77578, 4870, 1279, 2729, 1210, 273, 32983, 7204, 5512, 1161,
+    3362, 308, 2251, 1828, 295, 202, 2155, 761, 153, 12031, 7825, 295, 970, 430, 1908, 2727, 1466,
+    11907,
+];
+
+// Every square gets as many entries as there are subsets of its mask
+pub(crate) const ROOK_TABLE_SIZE: usize = 102_400;
+pub(crate) const BISHOP_TABLE_SIZE: usize = 5_248;
+
+// Checking the magics and filling the table takes a few seconds of constant evaluation, more
+// than the lint allows. It always ends, as find_magic gives up after MAX_ATTEMPTS candidates.
+#[cfg(not(all(feature = "pext", target_arch = "x86_64", target_feature = "bmi2")))]
+#[allow(long_running_const_eval)]
+static MAGICS: Magics = find_magics();
+
+struct Magics {
+    rook: [MagicRecord; 64],
+    bishop: [MagicRecord; 64],
+    attacks: [u64; ROOK_TABLE_SIZE + BISHOP_TABLE_SIZE],
+}
 
 #[derive(Copy, Clone, Debug)]
 struct MagicRecord {
@@ -74,22 +87,24 @@ struct MagicRecord {
     shift: u8,
 }
 
-fn bishop_attacks(occupied: u64, square_index: usize) -> u64 {
-    let record = MAGIC_BISHOP_TABLE[square_index];
-    let mut occ = occupied & record.mask;
-    occ *= record.magic;
-    occ >>= record.shift;
-    occ += record.index as u64;
-    MAGIC_ATTACK_TABLE[occ as usize]
+impl MagicRecord {
+    const fn table_index(&self, occupied: u64) -> usize {
+        self.index as usize + magic_function(occupied & self.mask, self.magic, self.shift)
+    }
+}
+
+// The pext feature swaps in the pext backend where the target supports it
+#[cfg(all(feature = "pext", target_arch = "x86_64", target_feature = "bmi2"))]
+pub use crate::pext_bitboard::{bishop_attacks, rook_attacks};
+
+#[cfg(not(all(feature = "pext", target_arch = "x86_64", target_feature = "bmi2")))]
+pub fn bishop_attacks(occupied: u64, square_index: usize) -> u64 {
+    MAGICS.attacks[MAGICS.bishop[square_index].table_index(occupied)]
 }
 
-fn rook_attacks(occupied: u64, square_index: usize) -> u64 {
-    let record = MAGIC_ROOK_TABLE[square_index];
-    let mut occ = occupied & record.mask;
-    occ *= record.magic;
-    occ >>= record.shift;
-    occ += record.index as u64;
-    MAGIC_ATTACK_TABLE[occ as usize]
+#[cfg(not(all(feature = "pext", target_arch = "x86_64", target_feature = "bmi2")))]
+pub fn rook_attacks(occupied: u64, square_index: usize) -> u64 {
+    MAGICS.attacks[MAGICS.rook[square_index].table_index(occupied)]
 }
 
 const fn generate_rook_mask(index: i64) -> u64 {
@@ -190,7 +205,7 @@ const fn generate_rook_attack(index: i64, blockers: u64) -> u64 {
     i = file + 1;
     while i <= 7 {
         bitboard |= 1 << (i + rank * 8);
-        if blockers & (1 << (file + i * 8)) != 0 {
+        if blockers & (1 << (i + rank * 8)) != 0 {
             break;
         };
         i += 1;
@@ -199,7 +214,7 @@ const fn generate_rook_attack(index: i64, blockers: u64) -> u64 {
     i = file - 1;
     while i >= 0 {
         bitboard |= 1 << (i + rank * 8);
-        if blockers & (1 << (file + i * 8)) != 0 {
+        if blockers & (1 << (i + rank * 8)) != 0 {
             break;
         };
         i -= 1;
@@ -260,44 +275,193 @@ const fn generate_bishop_attack(index: i64, blockers: u64) -> u64 {
     bitboard as u64
 }
 
-const fn magic_function(isolated_bit: u64, magic: u64, size: usize) -> usize {
-    ((isolated_bit * magic) >> (64 - size)) as usize
+const fn magic_function(occupied: u64, magic: u64, shift: u8) -> usize {
+    (occupied.wrapping_mul(magic) >> shift) as usize
 }
 
-const fn find_magic_multiplier(index: usize, _one_bits: usize) -> u64 {
-    let blockers = [0; 4096];
-    let mut solution = [0; 4096];
+const fn slider_mask(square: usize, bishop: bool) -> u64 {
+    if bishop {
+        generate_bishop_mask(square as i64, 0)
+    } else {
+        generate_rook_mask(square as i64)
+    }
+}
 
-    let mask = generate_rook_mask(index as i64);
-    let bits = mask.count_ones() as usize;
+pub(crate) const fn slider_attack(square: usize, blockers: u64, bishop: bool) -> u64 {
+    if bishop {
+        generate_bishop_attack(square as i64, blockers)
+    } else {
+        generate_rook_attack(square as i64, blockers)
+    }
+}
 
-    let mut i = 0;
-    while i < (1 << bits) {
-        //blockers[i] = generate_rook_attack(i as i64, blockers[i]);
-        solution[i] = generate_rook_attack(i as i64, blockers[i]);
-        i += 1;
+const fn find_magics() -> Magics {
+    let mut magics = Magics {
+        rook: [MagicRecord {
+            index: 0,
+            mask: 0,
+            magic: 0,
+            shift: 0,
+        }; 64],
+        bishop: [MagicRecord {
+            index: 0,
+            mask: 0,
+            magic: 0,
+            shift: 0,
+        }; 64],
+        attacks: [0; ROOK_TABLE_SIZE + BISHOP_TABLE_SIZE],
+    };
+    // The entries of every square follow those of the previous one, bishops after rooks
+    let mut index = 0;
+
+    let mut bishop = 0;
+    while bishop < 2 {
+        let mut square = 0;
+        while square < 64 {
+            let (mask, seed) = if bishop == 1 {
+                (BISHOP_MASK[square], BISHOP_SEEDS[square])
+            } else {
+                (ROOK_MASK[square], ROOK_SEEDS[square])
+            };
+            let record = MagicRecord {
+                index: index as u32,
+                mask,
+                magic: find_magic(square, bishop == 1, seed),
+                shift: (64 - mask.count_ones()) as u8,
+            };
+            fill_attacks(&mut magics.attacks, &record, square, bishop == 1);
+            index += 1 << mask.count_ones();
+
+            if bishop == 1 {
+                magics.bishop[square] = record;
+            } else {
+                magics.rook[square] = record;
+            }
+            square += 1;
+        }
+        bishop += 1;
+    }
+    magics
+}
+
+/// A random number with few bits set, as good magics tend to be
+const fn magic_candidate(rng: Rng) -> (u64, Rng) {
+    let (a, rng) = rng.const_rand_u64();
+    let (b, rng) = rng.const_rand_u64();
+    let (c, rng) = rng.const_rand_u64();
+    (a & b & c, rng)
+}
+
+/// Whether `magic` maps every subset of the mask of `square` to an entry that no subset with
+/// different attacks maps to
+const fn is_magic(square: usize, bishop: bool, magic: u64) -> bool {
+    let mask = slider_mask(square, bishop);
+    let shift = (64 - mask.count_ones()) as u8;
+
+    // Magics that spread the mask over too few of the high bits rarely work
+    if (mask.wrapping_mul(magic) >> 56).count_ones() < 6 {
+        return false;
     }
+
+    let mut used = [0; 4096];
+    let mut subset = 0u64;
     loop {
-        let _used = [0; 4096];
-        let magic = 0;
-        let failed = false;
-
-        let mut i = 0;
-        while i < (1 << bits) {
-            let _index = magic_function(blockers[i], magic, bits);
-            i += 1;
+        let index = magic_function(subset, magic, shift);
+        let attacks = slider_attack(square, subset, bishop);
+        // Attacks always contain at least one square, so zero marks an unused entry
+        if used[index] == 0 {
+            used[index] = attacks;
+        } else if used[index] != attacks {
+            return false;
         }
-        if failed == false {
+
+        subset = subset.wrapping_sub(mask) & mask;
+        if subset == 0 {
+            return true;
+        }
+    }
+}
+
+// How many candidates find_magic tries. Good seeds need only one, a bad one should fail the
+// build instead of keeping constant evaluation busy.
+const MAX_ATTEMPTS: usize = 16;
+
+/// Tries candidates from the generator seeded with `seed` until one is a magic
+const fn find_magic(square: usize, bishop: bool, seed: u64) -> u64 {
+    let mut rng = Rng::new(seed);
+    let mut attempts = 0;
+    while attempts < MAX_ATTEMPTS {
+        let (magic, next) = magic_candidate(rng);
+        rng = next;
+        if is_magic(square, bishop, magic) {
             return magic;
         }
+        attempts += 1;
+    }
+    panic!("No magic found from the seed, the seeds need to be searched again");
+}
+
+/// Prints the first seed of every square whose first candidate is a magic, rooks then bishops
+pub fn print_seeds() {
+    for &bishop in &[false, true] {
+        let seeds: Vec<_> = (0..64)
+            .map(|square| {
+                (0..)
+                    .find(|&seed| {
+                        let (magic, _) = magic_candidate(Rng::new(seed));
+                        is_magic(square, bishop, magic)
+                    })
+                    .unwrap()
+            })
+            .collect();
+        println!("{:?}", seeds);
+    }
+}
+
+const fn fill_attacks(table: &mut [u64], record: &MagicRecord, square: usize, bishop: bool) {
+    let mut subset = 0u64;
+    loop {
+        table[record.table_index(subset)] = slider_attack(square, subset, bishop);
+        subset = subset.wrapping_sub(record.mask) & record.mask;
+        if subset == 0 {
+            break;
+        }
     }
 }
 
 #[cfg(test)]
 mod test {
-    use crate::bitboard::print_bitboard;
-    use crate::magic_bitboard::generate_bishop_mask;
+    use crate::magic_bitboard::{
+        bishop_attacks, generate_bishop_attack, generate_rook_attack, is_magic, magic_candidate,
+        rook_attacks, BISHOP_SEEDS, ROOK_SEEDS,
+    };
+    use crate::util::Rng;
 
     #[test]
-    fn test_bishop() {}
+    fn test_seeds() {
+        for square in 0..64 {
+            let (magic, _) = magic_candidate(Rng::new(ROOK_SEEDS[square]));
+            assert!(is_magic(square, false, magic), "rook {}", square);
+            let (magic, _) = magic_candidate(Rng::new(BISHOP_SEEDS[square]));
+            assert!(is_magic(square, true, magic), "bishop {}", square);
+        }
+    }
+
+    #[test]
+    fn test_attacks() {
+        let mut rng = Rng::new(42);
+        for _ in 0..100 {
+            let occupied = rng.rand_u64() & rng.rand_u64();
+            for square in 0..64 {
+                assert_eq!(
+                    rook_attacks(occupied, square),
+                    generate_rook_attack(square as i64, occupied)
+                );
+                assert_eq!(
+                    bishop_attacks(occupied, square),
+                    generate_bishop_attack(square as i64, occupied)
+                );
+            }
+        }
+    }
 }
@@ -4,28 +4,40 @@ use std::{error::Error, str::from_utf8};
 
 use crate::interface::*;
 use crate::uci::{ResponseType, UCIDriver};
-use bitboard::{generate_moves, perft, BitBoardMove, BitBoardState};
+use bitboard::{generate_moves, perft, BitBoardMove, BitBoardState, GameStatus};
 use board::{Board, Color};
-use clap::{App, Arg};
+use clap::{App, Arg, SubCommand};
+use history::GameHistory;
 use tokio::{
     fs::File,
-    io::{self, stdin, stdout, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader, BufWriter},
+    io::{
+        self, stdin, stdout, AsyncBufReadExt, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader,
+        BufWriter,
+    },
+    sync::mpsc::unbounded_channel,
 };
 
+mod bench;
 mod bitboard;
 mod board;
 mod evaluation;
+mod history;
 mod interface;
 mod magic_bitboard;
 mod move_gen;
+#[cfg(all(feature = "pext", target_arch = "x86_64", target_feature = "bmi2"))]
+mod pext_bitboard;
 mod search;
+mod time_manager;
+mod transposition;
 mod uci;
 mod util;
+mod zobrist;
 
-pub const APPLICATION_VERSION: &'static str = "0.0.1";
-pub const APPLICATION_NAME: &'static str = "Grants's AI";
-pub const APPLICATION_AUTHOR: &'static str = "Grant";
-pub const APPLICATION_ABOUT: &'static str = "This is a chess AI";
+pub const APPLICATION_VERSION: &str = "0.0.1";
+pub const APPLICATION_NAME: &str = "Grants's AI";
+pub const APPLICATION_AUTHOR: &str = "Grant";
+pub const APPLICATION_ABOUT: &str = "This is a chess AI";
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -46,8 +58,31 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 .help("This suplies a custom fen for the cli interface")
                 .takes_value(true),
         )
+        .subcommand(
+            SubCommand::with_name("bench")
+                .about("Searches a fixed set of positions and prints the nodes searched")
+                .arg(Arg::with_name("depth").help("The depth of every search")),
+        )
+        .subcommand(
+            SubCommand::with_name("seeds")
+                .about("Searches new seeds for the magic bitboards and prints them"),
+        )
         .get_matches();
 
+    if let Some(bench) = matches.subcommand_matches("bench") {
+        let depth = match bench.value_of("depth") {
+            Some(depth) => depth.parse()?,
+            None => bench::BENCH_DEPTH,
+        };
+        bench::bench(depth);
+        return Ok(());
+    }
+
+    if matches.subcommand_matches("seeds").is_some() {
+        magic_bitboard::print_seeds();
+        return Ok(());
+    }
+
     let cli_inteface = matches.occurrences_of("cli");
     let fen = matches.value_of("fen");
 
@@ -57,139 +92,181 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let mut log = tokio::fs::File::create("log.txt").await?;
 
     if cli_inteface == 0 {
-        let mut uci_driver = UCIDriver::new();
+        let (output, mut search_output) = unbounded_channel();
+        let mut uci_driver = UCIDriver::new(output);
+        let mut lines = reader.lines();
 
         loop {
-            reader.read_line(&mut buffer).await?;
-            log.write(b"> ").await?;
-            log.write(buffer.as_bytes()).await?;
+            // Keep reading commands while a search is running so stop and isready get through
+            let line = tokio::select! {
+                line = lines.next_line() => match line? {
+                    Some(line) => line,
+                    // The GUI closed stdin, there is nobody left to play for
+                    None => String::from("quit"),
+                },
+                Some(response) = search_output.recv() => {
+                    write_response(&mut writer, &mut log, &response).await?;
+                    continue;
+                }
+            };
 
-            match uci_driver.parse_command(&buffer) {
+            log.write_all(b"> ").await?;
+            log.write_all(line.as_bytes()).await?;
+            log.write_all(b"\n").await?;
+
+            match uci_driver.parse_command(&line) {
                 ResponseType::Response(response) => {
-                    writer.write(response.as_bytes()).await?;
-                    writer.write(b"\n").await?;
-                    writer.flush().await?;
-                    log.write(response.as_bytes()).await?;
-                    log.write(b"\n").await?;
-                    log.flush().await?;
+                    write_response(&mut writer, &mut log, &response).await?;
                 }
                 ResponseType::Log(s) => {
-                    log.write(b"!").await?;
-                    log.write(s.as_bytes()).await?;
-                    log.write(b"\n").await?;
+                    log.write_all(b"!").await?;
+                    log.write_all(s.as_bytes()).await?;
+                    log.write_all(b"\n").await?;
                     log.flush().await?;
                 }
                 ResponseType::ResponseLog(response, s) => {
-                    writer.write(response.as_bytes()).await?;
-                    writer.write(b"\n").await?;
+                    writer.write_all(response.as_bytes()).await?;
+                    writer.write_all(b"\n").await?;
                     writer.flush().await?;
-                    log.write(b"!").await?;
-                    log.write(s.as_bytes()).await?;
-                    log.write(b"\n").await?;
-                    log.write(response.as_bytes()).await?;
-                    log.write(b"\n").await?;
+                    log.write_all(b"!").await?;
+                    log.write_all(s.as_bytes()).await?;
+                    log.write_all(b"\n").await?;
+                    log.write_all(response.as_bytes()).await?;
+                    log.write_all(b"\n").await?;
                     log.flush().await?;
                 }
                 ResponseType::Nothing => {
-                    log.write(b"!Nothing\n").await?;
+                    log.write_all(b"!Nothing\n").await?;
                 }
                 ResponseType::Quit => {
-                    log.write(b"!Quit\n").await?;
+                    log.write_all(b"!Quit\n").await?;
                     break;
                 }
             };
+        }
 
-            buffer.clear();
+        // A search stopped by quit still reports its best move, dropping the driver
+        // closes the channel once that has been received
+        drop(uci_driver);
+        while let Some(response) = search_output.recv().await {
+            write_response(&mut writer, &mut log, &response).await?;
         }
     } else {
         let mut bit_board = if let Some(f) = fen {
-            BitBoardState::from_fen(f)?
+            BitBoardState::from_fen_strict(f)?
         } else {
             BitBoardState::new()
         };
 
+        let mut history = GameHistory::new();
+        let mut last_move = None;
+
         'main_loop: loop {
+            let status = bit_board.game_status(&history);
+            if status != GameStatus::Ongoing {
+                println!("\x1B[2J");
+                bit_board.bitboard.print_board(None, None);
+                println!("{}", status);
+                break;
+            }
+
             let moves = generate_moves(&bit_board);
 
-            let (start, end) = 'start_end_loop: loop {
+            let m = 'start_end_loop: loop {
                 println!("\x1B[2J");
                 bit_board.bitboard.print_board(None, None);
+                if let Some(san) = &last_move {
+                    println!("Last move: {}", san);
+                }
 
                 writer
-                    .write(format!("({}) From: ", &bit_board.active_color).as_bytes())
+                    .write_all(format!("({}) From: ", &bit_board.active_color).as_bytes())
                     .await?;
                 writer.flush().await?;
 
                 buffer.clear();
                 reader.read_line(&mut buffer).await?;
 
+                // A move can be typed in algebraic notation, or picked by its squares
+                if let Ok(m) = bit_board.parse_san(buffer.trim()) {
+                    break m;
+                }
+
                 match buffer.trim().as_bytes() {
                     b"q" => break 'main_loop,
                     [_, _] => {
-                        if let Ok(start) = algebraic_to_index(&buffer.trim()[0..2].as_bytes()) {
-                            break (
-                                start,
-                                loop {
-                                    println!("\x1B[2J");
-                                    bit_board.bitboard.print_board(Some(start), Some(&moves));
+                        if let Ok(start) = algebraic_to_index(&buffer.trim().as_bytes()[0..2]) {
+                            let end = loop {
+                                println!("\x1B[2J");
+                                bit_board.bitboard.print_board(Some(start), Some(&moves));
 
-                                    let algebraic = index_to_algebraic(start);
-                                    let algebraic_str = from_utf8(&algebraic)?;
+                                let algebraic = index_to_algebraic(start);
+                                let algebraic_str = from_utf8(&algebraic)?;
 
-                                    writer
-                                        .write(
-                                            format!(
-                                                "({}) From: {} End: ",
-                                                &bit_board.active_color, algebraic_str
-                                            )
-                                            .as_bytes(),
+                                writer
+                                    .write_all(
+                                        format!(
+                                            "({}) From: {} End: ",
+                                            &bit_board.active_color, algebraic_str
                                         )
-                                        .await?;
-                                    writer.flush().await?;
+                                        .as_bytes(),
+                                    )
+                                    .await?;
+                                writer.flush().await?;
 
-                                    buffer.clear();
-                                    reader.read_line(&mut buffer).await?;
+                                buffer.clear();
+                                reader.read_line(&mut buffer).await?;
 
-                                    match buffer.trim().as_bytes() {
-                                        b"b" => continue 'start_end_loop,
-                                        b"q" => break 'main_loop,
-                                        [_, _] => {
-                                            if let Ok(end) =
-                                                algebraic_to_index(&buffer.trim()[0..2].as_bytes())
-                                            {
-                                                if moves.contains(&BitBoardMove::new(
-                                                    start as u16,
-                                                    end as u16,
-                                                    0,
-                                                )) {
-                                                    break end;
-                                                }
+                                match buffer.trim().as_bytes() {
+                                    b"b" => continue 'start_end_loop,
+                                    b"q" => break 'main_loop,
+                                    [_, _] => {
+                                        if let Ok(end) =
+                                            algebraic_to_index(&buffer.trim().as_bytes()[0..2])
+                                        {
+                                            if moves.contains(&BitBoardMove::new(
+                                                start as u16,
+                                                end as u16,
+                                                0,
+                                            )) {
+                                                break end;
                                             }
                                         }
-                                        _ => {}
                                     }
-                                },
-                            );
+                                    _ => {}
+                                }
+                            };
+
+                            let m = BitBoardMove::new(start as u16, end as u16, 0);
+                            break *moves.iter().find(|&other| other == &m).unwrap();
                         }
                     }
                     _ => {}
                 }
             };
 
-            let pos = moves
-                .iter()
-                .position(|m| m == &BitBoardMove::new(start as u16, end as u16, 0))
-                .unwrap();
-
-            bit_board.apply_move(&moves[pos]);
-            bit_board.mirror_board();
-            //bit_board.change_side();
+            last_move = Some(bit_board.move_to_san(&m));
+            history.push(&bit_board);
+            bit_board.make_move(&m);
         }
     }
 
     Ok(())
 }
 
+async fn write_response<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    log: &mut File,
+    response: &str,
+) -> io::Result<()> {
+    writer.write_all(response.as_bytes()).await?;
+    writer.write_all(b"\n").await?;
+    writer.flush().await?;
+    log.write_all(response.as_bytes()).await?;
+    log.write_all(b"\n").await?;
+    log.flush().await
+}
+
 // if false {
 //     let mut siv = Cursive::new();
 //     siv.load_toml(include_str!("theme.toml")).unwrap();
@@ -151,11 +151,11 @@ pub fn generate_pseudo_legal_moves(board: &Board) -> Vec<Move> {
             if color == board.active_color {
                 match piece {
                     Piece::Bishop | Piece::Rook | Piece::Queen => {
-                        gen_sliding_moves(&mut moves, &board, start)
+                        gen_sliding_moves(&mut moves, board, start)
                     }
-                    Piece::Knight => gen_knight_moves(&mut moves, &board, start),
-                    Piece::King => gen_king_moves(&mut moves, &board, start),
-                    Piece::Pawn => gen_pawn_moves(&mut moves, &board, start),
+                    Piece::Knight => gen_knight_moves(&mut moves, board, start),
+                    Piece::King => gen_king_moves(&mut moves, board, start),
+                    Piece::Pawn => gen_pawn_moves(&mut moves, board, start),
                 };
             }
         }
@@ -203,21 +203,17 @@ fn gen_pawn_moves(moves: &mut Vec<Move>, board: &Board, start: usize) {
 
         // Single advance
         let target_square = (start as i32 + DIRECTION_OFFSETS[direction]) as usize;
-        if target_square < 64 {
-            if None == board.pieces[target_square] {
-                if target_square / 8 == 0 || target_square / 8 == 7 {
-                    moves.push(Move::new_flag(start, target_square, MoveFlag::PromoteQueen))
-                } else {
-                    moves.push(Move::new(start, target_square));
-                }
-                // Initial double advance
-                if color == Color::White && start / 8 == 1
-                    || color == Color::Black && start / 8 == 6
-                {
-                    let target_square = (start as i32 + DIRECTION_OFFSETS[direction] * 2) as usize;
-                    if None == board.pieces[target_square] {
-                        moves.push(Move::new_flag(start, target_square, MoveFlag::InitialMove));
-                    }
+        if target_square < 64 && board.pieces[target_square].is_none() {
+            if target_square / 8 == 0 || target_square / 8 == 7 {
+                moves.push(Move::new_flag(start, target_square, MoveFlag::PromoteQueen))
+            } else {
+                moves.push(Move::new(start, target_square));
+            }
+            // Initial double advance
+            if color == Color::White && start / 8 == 1 || color == Color::Black && start / 8 == 6 {
+                let target_square = (start as i32 + DIRECTION_OFFSETS[direction] * 2) as usize;
+                if board.pieces[target_square].is_none() {
+                    moves.push(Move::new_flag(start, target_square, MoveFlag::InitialMove));
                 }
             }
         }
@@ -365,7 +361,7 @@ pub fn perft(board: &mut Board, depth: usize) -> usize {
         nodes += perft(board, depth - 1);
         board.revert_last_move(m);
     }
-    return nodes;
+    nodes
 }
 
 #[cfg(test)]
@@ -0,0 +1,65 @@
+// Sliding attacks indexed with the BMI2 pext instruction, which packs the occupied squares under
+// the mask of a square into an index directly, so no magics are needed. Only compiled with the
+// pext feature on x86_64 targets that have BMI2 enabled, magic_bitboard.rs is used otherwise.
+
+use crate::magic_bitboard::{
+    slider_attack, BISHOP_MASK, BISHOP_TABLE_SIZE, ROOK_MASK, ROOK_TABLE_SIZE,
+};
+use std::arch::x86_64::_pext_u64;
+
+static PEXT_TABLES: PextTables = fill_tables();
+
+struct PextTables {
+    rook_index: [usize; 64],
+    bishop_index: [usize; 64],
+    attacks: [u64; ROOK_TABLE_SIZE + BISHOP_TABLE_SIZE],
+}
+
+const fn fill_tables() -> PextTables {
+    let mut tables = PextTables {
+        rook_index: [0; 64],
+        bishop_index: [0; 64],
+        attacks: [0; ROOK_TABLE_SIZE + BISHOP_TABLE_SIZE],
+    };
+    let mut index = 0;
+
+    let mut bishop = 0;
+    while bishop < 2 {
+        let mut square = 0;
+        while square < 64 {
+            let mask = if bishop == 1 {
+                tables.bishop_index[square] = index;
+                BISHOP_MASK[square]
+            } else {
+                tables.rook_index[square] = index;
+                ROOK_MASK[square]
+            };
+
+            // Subsets are walked in the order of their pext index
+            let mut subset = 0u64;
+            loop {
+                tables.attacks[index] = slider_attack(square, subset, bishop == 1);
+                index += 1;
+                subset = subset.wrapping_sub(mask) & mask;
+                if subset == 0 {
+                    break;
+                }
+            }
+            square += 1;
+        }
+        bishop += 1;
+    }
+    tables
+}
+
+pub fn bishop_attacks(occupied: u64, square_index: usize) -> u64 {
+    // Safe as this module is only built for targets with BMI2
+    let index = unsafe { _pext_u64(occupied, BISHOP_MASK[square_index]) } as usize;
+    PEXT_TABLES.attacks[PEXT_TABLES.bishop_index[square_index] + index]
+}
+
+pub fn rook_attacks(occupied: u64, square_index: usize) -> u64 {
+    // Safe as this module is only built for targets with BMI2
+    let index = unsafe { _pext_u64(occupied, ROOK_MASK[square_index]) } as usize;
+    PEXT_TABLES.attacks[PEXT_TABLES.rook_index[square_index] + index]
+}
@@ -1,151 +1,1473 @@
-use crate::bitboard::{generate_moves, BitBoard, BitBoardMove, BitBoardState};
-use crate::board::Color;
-use std::collections::BinaryHeap;
+use crate::bitboard::{generate_captures, generate_moves, BitBoardMove, BitBoardState};
+use crate::board::Piece;
 use crate::evaluation::evaluate_bitboard;
-use std::cmp::Ordering;
-use rayon::prelude::*;
-use std::sync::{Arc, Mutex};
-use crate::util::Rng;
+use crate::history::GameHistory;
+use crate::time_manager::{SearchLimits, TimeManager};
+use crate::transposition::{Bound, TranspositionTable, MAX_MEGABYTES};
+use std::sync::atomic::{AtomicBool, Ordering as AtomicOrdering};
+use std::sync::Arc;
 
-pub struct SearchDriver {
-    transposition_table: Vec<i64>,
-    zobrist_table: [[u64; 64]; 12]
+// How many nodes are searched between two looks at the clock
+const TIME_CHECK_INTERVAL: u64 = 1024;
+// Root moves are only announced with `currmove` once the search has run this long
+const CURRMOVE_DELAY: u64 = 1000;
+// Iterations from this depth on start with a window this many centipawns to either side of the
+// score of the previous one. Scores swing by about a pawn between odd and even depths, narrower
+// windows fail more often than they save.
+const ASPIRATION_DEPTH: usize = 5;
+const ASPIRATION_WINDOW: i64 = 100;
+
+pub const INFINITY: i64 = 1_000_000;
+/// Score of delivering mate on the current move, every ply to get there costs one point
+pub const MATE: i64 = 100_000;
+
+/// A score as the UCI protocol reports it, always from the side to move at the root
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Score {
+    /// Centipawns
+    Cp(i64),
+    /// Mate in this many moves, negative when the engine is getting mated
+    Mate(i64),
 }
 
-impl SearchDriver {
-    pub fn new(size: usize) -> Self {
-        let mut rng = Rng::unix_seed();
-        let mut zobrist_table = [[0; 64]; 12];
-        //TODO: Add en passant and castling
+impl Score {
+    pub fn from_value(value: i64) -> Self {
+        if value.abs() >= MATE - MAX_PLY as i64 {
+            let moves = (MATE - value.abs() + 1) / 2;
+            Score::Mate(if value > 0 { moves } else { -moves })
+        } else {
+            Score::Cp(value)
+        }
+    }
+}
+
+/// Progress of a running search, every field that is set ends up in an `info` line
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SearchInfo {
+    pub depth: Option<usize>,
+    pub seldepth: Option<usize>,
+    /// Rank of the line when more than the best move is searched
+    pub multipv: Option<usize>,
+    pub score: Option<Score>,
+    pub nodes: Option<u64>,
+    pub nps: Option<u64>,
+    /// Milliseconds since the search started
+    pub time: Option<u64>,
+    pub hashfull: Option<usize>,
+    pub currmove: Option<BitBoardMove>,
+    pub currmovenumber: Option<usize>,
+    pub pv: Vec<BitBoardMove>,
+}
+
+pub type InfoCallback = Box<dyn FnMut(&SearchInfo) + Send>;
+
+// Deepest ply a line can reach
+const MAX_PLY: usize = 128;
+
+/// Triangular table holding the principal variation found below every ply
+struct PvTable {
+    moves: [[BitBoardMove; MAX_PLY]; MAX_PLY],
+    length: [usize; MAX_PLY],
+}
+
+impl PvTable {
+    fn new() -> Self {
+        Self {
+            moves: [[BitBoardMove::new(0, 0, 0); MAX_PLY]; MAX_PLY],
+            length: [0; MAX_PLY],
+        }
+    }
+
+    fn clear(&mut self, ply: usize) {
+        self.length[ply] = ply;
+    }
+
+    /// `m` became the best move at `ply`, so its line is `m` followed by the line of the child
+    fn update(&mut self, ply: usize, m: BitBoardMove) {
+        let child_length = self.length[ply + 1].max(ply + 1);
+        self.moves[ply][ply] = m;
+        for i in ply + 1..child_length {
+            self.moves[ply][i] = self.moves[ply + 1][i];
+        }
+        self.length[ply] = child_length;
+    }
+
+    fn line(&self, ply: usize) -> &[BitBoardMove] {
+        &self.moves[ply][ply..self.length[ply]]
+    }
+}
+
+// Margins of the selective search are in centipawns per ply of depth left, the depths are the
+// most that is left for a technique to be used
+const REVERSE_FUTILITY_DEPTH: usize = 6;
+const REVERSE_FUTILITY_MARGIN: i64 = 90;
+const RAZORING_DEPTH: usize = 3;
+const RAZORING_MARGIN: i64 = 250;
+const FUTILITY_DEPTH: usize = 5;
+const FUTILITY_MARGIN: i64 = 100;
+const LATE_MOVE_PRUNING_DEPTH: usize = 6;
+// The least depth left for a null move search
+const NULL_MOVE_DEPTH: usize = 3;
+// The least depth left to test whether the hash move is singular, and how much shallower the
+// hash entry may have been searched
+const SINGULAR_DEPTH: usize = 8;
+const SINGULAR_TT_DEPTH: usize = 3;
+
+/// The selective search techniques, each can be turned off on its own to measure what it is
+/// worth
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Pruning {
+    pub null_move: bool,
+    pub late_move_reductions: bool,
+    pub reverse_futility: bool,
+    pub futility: bool,
+    pub razoring: bool,
+    pub late_move_pruning: bool,
+}
+
+impl Default for Pruning {
+    fn default() -> Self {
+        Self {
+            null_move: true,
+            late_move_reductions: true,
+            reverse_futility: true,
+            futility: true,
+            razoring: true,
+            late_move_pruning: true,
+        }
+    }
+}
+
+// Piece values captures are ordered by, indexed like `Piece`
+const ORDER_VALUES: [i32; 6] = [1_000, 900, 500, 300, 300, 100];
+// History scores stay within this bound, so a move can always be overtaken by a newer one
+const MAX_HISTORY: i32 = 16_384;
 
-        for i in 0..64 {
-            for j in 0..12 {
-                zobrist_table[j][i] = rng.rand_u64();
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Stage {
+    HashMove,
+    GoodCaptures,
+    Refutations,
+    Quiets,
+    BadCaptures,
+    Done,
+}
+
+/// Hands out the moves of a node a stage at a time: the hash move, captures that do not lose
+/// material by MVV-LVA, the killers and the countermove, the other quiet moves by their history and
+/// finally the captures that lose material. Quiet moves are only scored once the captures did
+/// not produce a cutoff.
+struct MovePicker {
+    stage: Stage,
+    hash_move: Option<BitBoardMove>,
+    refutations: [Option<BitBoardMove>; 3],
+    captures: Vec<(BitBoardMove, i32)>,
+    bad_captures: Vec<(BitBoardMove, i32)>,
+    quiets: Vec<(BitBoardMove, i32)>,
+}
+
+impl MovePicker {
+    fn new(
+        bitboard: &BitBoardState,
+        moves: Vec<BitBoardMove>,
+        hash_move: Option<BitBoardMove>,
+        refutations: [Option<BitBoardMove>; 3],
+    ) -> Self {
+        let mut picker = Self {
+            stage: Stage::HashMove,
+            hash_move: None,
+            refutations,
+            captures: Vec::with_capacity(moves.len()),
+            bad_captures: Vec::new(),
+            quiets: Vec::with_capacity(moves.len()),
+        };
+
+        for m in moves {
+            if hash_move.is_some_and(|hash_move| hash_move.to_u16() == m.to_u16()) {
+                picker.hash_move = Some(m);
+            } else if is_quiet(&m) {
+                picker.quiets.push((m, 0));
+            } else {
+                let attacker = bitboard
+                    .bitboard
+                    .get_piece(m.get_from() as usize)
+                    .map_or(Piece::Pawn, |(_, piece)| piece);
+                let victim = match bitboard.bitboard.get_piece(m.get_to() as usize) {
+                    Some((_, piece)) => ORDER_VALUES[piece as usize],
+                    None if m.is_capture() => ORDER_VALUES[Piece::Pawn as usize],
+                    None => 0,
+                };
+                let promotion = m.promotion().map_or(0, |piece| {
+                    ORDER_VALUES[piece as usize] - ORDER_VALUES[Piece::Pawn as usize]
+                });
+
+                // Most valuable victim first, least valuable attacker to break ties
+                let score = 10 * (victim + promotion) - ORDER_VALUES[attacker as usize];
+                if bitboard.see_ge(&m, 0) {
+                    picker.captures.push((m, score));
+                } else {
+                    picker.bad_captures.push((m, score));
+                }
             }
         }
+        picker
+    }
+
+    /// The next move to search, `quiet_history` scores the quiet moves of the side to move
+    fn next(&mut self, quiet_history: &[[i32; 64]; 64]) -> Option<BitBoardMove> {
+        loop {
+            match self.stage {
+                Stage::HashMove => {
+                    self.stage = Stage::GoodCaptures;
+                    if self.hash_move.is_some() {
+                        return self.hash_move;
+                    }
+                }
+                Stage::GoodCaptures => match pop_best(&mut self.captures) {
+                    Some(m) => return Some(m),
+                    None => self.stage = Stage::Refutations,
+                },
+                Stage::Refutations => {
+                    // Only quiet moves that are legal here and were not searched yet count
+                    let quiets = &mut self.quiets;
+                    for refutation in self.refutations.iter_mut() {
+                        let index = refutation.take().and_then(|refutation| {
+                            quiets
+                                .iter()
+                                .position(|(m, _)| m.to_u16() == refutation.to_u16())
+                        });
+                        if let Some(index) = index {
+                            return Some(quiets.swap_remove(index).0);
+                        }
+                    }
+
+                    for (m, score) in self.quiets.iter_mut() {
+                        *score = quiet_history[m.get_from() as usize][m.get_to() as usize];
+                    }
+                    self.stage = Stage::Quiets;
+                }
+                Stage::Quiets => match pop_best(&mut self.quiets) {
+                    Some(m) => return Some(m),
+                    None => self.stage = Stage::BadCaptures,
+                },
+                Stage::BadCaptures => match pop_best(&mut self.bad_captures) {
+                    Some(m) => return Some(m),
+                    None => self.stage = Stage::Done,
+                },
+                Stage::Done => return None,
+            }
+        }
+    }
+}
+
+/// Removes the move with the highest score
+fn pop_best(moves: &mut Vec<(BitBoardMove, i32)>) -> Option<BitBoardMove> {
+    let index = moves
+        .iter()
+        .enumerate()
+        .max_by_key(|(_, (_, score))| *score)
+        .map(|(index, _)| index)?;
+    Some(moves.swap_remove(index).0)
+}
+
+fn is_quiet(m: &BitBoardMove) -> bool {
+    !m.is_capture() && m.promotion().is_none()
+}
+
+/// Whether the side to move has pieces besides pawns. Without them zugzwang is common, so
+/// passing is not a safe guess of the worst case.
+fn has_non_pawn_material(bitboard: &BitBoardState) -> bool {
+    [Piece::Queen, Piece::Rook, Piece::Bishop, Piece::Knight]
+        .iter()
+        .any(|&piece| bitboard.bitboard.get_set(bitboard.active_color, piece) != 0)
+}
+
+/// How many plies late moves are reduced by, by depth left and number of moves searched
+fn reduction_table() -> Box<[[u8; 64]; 64]> {
+    let mut table = Box::new([[0; 64]; 64]);
+    for (depth, row) in table.iter_mut().enumerate().skip(1) {
+        for (moves, reduction) in row.iter_mut().enumerate().skip(1) {
+            *reduction = (0.75 + (depth as f64).ln() * (moves as f64).ln() / 2.25) as u8;
+        }
+    }
+    table
+}
+
+/// Moves `entry` towards `bonus`, slower the closer it already is to the bound
+fn update_history(entry: &mut i32, bonus: i32) {
+    *entry += bonus - *entry * bonus.abs() / MAX_HISTORY;
+}
 
+/// Flags the UCI thread uses to talk to a search running on another thread
+#[derive(Debug, Default)]
+pub struct SearchSignals {
+    stop: AtomicBool,
+    ponder: AtomicBool,
+}
+
+impl SearchSignals {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Prepares the flags for a new search
+    pub fn reset(&self, ponder: bool) {
+        self.stop.store(false, AtomicOrdering::SeqCst);
+        self.ponder.store(ponder, AtomicOrdering::SeqCst);
+    }
+
+    pub fn stop(&self) {
+        self.stop.store(true, AtomicOrdering::SeqCst);
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.stop.load(AtomicOrdering::Relaxed)
+    }
+
+    pub fn ponder_hit(&self) {
+        self.ponder.store(false, AtomicOrdering::SeqCst);
+    }
+
+    pub fn is_pondering(&self) -> bool {
+        self.ponder.load(AtomicOrdering::Relaxed)
+    }
+}
+
+pub struct SearchDriver {
+    transposition_table: Arc<TranspositionTable>,
+    time_manager: TimeManager,
+    signals: Arc<SearchSignals>,
+    info_callback: Option<InfoCallback>,
+    pv: PvTable,
+    // Moves of the root position, best first as of the last search of them
+    root_moves: Vec<RootMove>,
+    // Line of the last iteration, searched first by the next one
+    previous_pv: Vec<BitBoardMove>,
+    follow_pv: bool,
+    // Positions of the game and of the line being searched, to find repetitions
+    history: GameHistory,
+    // Quiet moves that caused a cutoff at each ply
+    killers: [[Option<BitBoardMove>; 2]; MAX_PLY],
+    // The quiet move that refuted a move, by the from and to square of that move
+    countermoves: Box<[[Option<BitBoardMove>; 64]; 64]>,
+    // How often quiet moves caused cutoffs, by side to move, from and to square
+    quiet_history: Box<[[[i32; 64]; 64]; 2]>,
+    // The move made at each ply of the line being searched, `None` for a null move
+    move_stack: [Option<BitBoardMove>; MAX_PLY],
+    // The move left out at each ply while testing whether it is singular
+    excluded: [Option<BitBoardMove>; MAX_PLY],
+    // Plies added by extensions along the line being searched, at most half the root depth
+    extensions: [usize; MAX_PLY],
+    root_depth: usize,
+    reductions: Box<[[u8; 64]; 64]>,
+    pruning: Pruning,
+    multi_pv: usize,
+    nodes: u64,
+    seldepth: usize,
+    stopped: bool,
+}
+
+impl SearchDriver {
+    /// `hash` is the size of the transposition table in megabytes
+    pub fn new(hash: usize) -> Self {
         Self {
-            transposition_table: Vec::with_capacity(2usize.pow(size as u32)),
-            zobrist_table
+            transposition_table: Arc::new(TranspositionTable::new(hash)),
+            time_manager: TimeManager::default(),
+            signals: Arc::new(SearchSignals::new()),
+            info_callback: None,
+            pv: PvTable::new(),
+            root_moves: Vec::new(),
+            previous_pv: Vec::new(),
+            follow_pv: false,
+            history: GameHistory::new(),
+            killers: [[None; 2]; MAX_PLY],
+            countermoves: Box::new([[None; 64]; 64]),
+            quiet_history: Box::new([[[0; 64]; 64]; 2]),
+            move_stack: [None; MAX_PLY],
+            excluded: [None; MAX_PLY],
+            extensions: [0; MAX_PLY],
+            root_depth: 0,
+            reductions: reduction_table(),
+            pruning: Pruning::default(),
+            multi_pv: 1,
+            nodes: 0,
+            seldepth: 0,
+            stopped: false,
         }
     }
 
-    pub fn best_move(&mut self, bitboard: &BitBoardState, depth: usize) -> BitBoardMove {
-        let mut moves = self.evaluate_moves(bitboard, depth);
-        moves.pop().unwrap().m
+    pub fn nodes(&self) -> u64 {
+        self.nodes
     }
 
-    pub fn alpha_beta_max(&mut self, bitboard: &BitBoardState, color: Color, mut alpha: i64, beta: i64, depth: usize) -> i64 {
-        if depth == 0 {
-            let eval = evaluate_bitboard(bitboard, color);
-            let table_len = self.transposition_table.len();
-            self.transposition_table[bitboard.zobrist_hash(self.zobrist_table) as usize % table_len] = eval;
-            return eval;
+    /// The signals can be used to stop the search or end pondering from another thread
+    pub fn signals(&self) -> Arc<SearchSignals> {
+        Arc::clone(&self.signals)
+    }
+
+    /// Every progress report of the search is passed to `callback`
+    pub fn set_info_callback<F: FnMut(&SearchInfo) + Send + 'static>(&mut self, callback: F) {
+        self.info_callback = Some(Box::new(callback));
+    }
+
+    /// How many of the best root moves are searched and reported with their own line
+    pub fn set_multi_pv(&mut self, multi_pv: usize) {
+        self.multi_pv = multi_pv.max(1);
+    }
+
+    pub fn set_pruning(&mut self, pruning: Pruning) {
+        self.pruning = pruning;
+    }
+
+    /// Resizes the transposition table, which also clears it
+    pub fn set_hash(&mut self, hash: usize) {
+        let hash = hash.clamp(1, MAX_MEGABYTES);
+        if self.transposition_table.megabytes() != hash {
+            self.transposition_table = Arc::new(TranspositionTable::new(hash));
         }
+    }
+
+    pub fn clear_hash(&mut self) {
+        self.transposition_table.clear();
+    }
 
-        if self.transposition_table[bitboard.zobrist_hash(self.zobrist_table) as usize % self.transposition_table.len()] != 0 {
-            return self.transposition_table[bitboard.zobrist_hash(self.zobrist_table) as usize % self.transposition_table.len()]
+    fn report(&mut self, info: SearchInfo) {
+        if let Some(callback) = &mut self.info_callback {
+            callback(&info);
         }
+    }
+
+    /// The move of the previous principal variation at `ply`, while the search is still
+    /// walking along that line
+    fn pv_move(&mut self, ply: usize, moves: &[BitBoardMove]) -> Option<BitBoardMove> {
+        if !self.follow_pv {
+            return None;
+        }
+
+        let pv_move = self.previous_pv.get(ply).and_then(|pv_move| {
+            moves
+                .iter()
+                .find(|m| m.to_u16() == pv_move.to_u16())
+                .copied()
+        });
+        if pv_move.is_none() {
+            self.follow_pv = false;
+        }
+        pv_move
+    }
+
+    /// A quiet move caused a cutoff, so it is tried early in similar positions. The quiet
+    /// moves searched before it get a malus.
+    fn update_quiet_stats(
+        &mut self,
+        bitboard: &BitBoardState,
+        m: BitBoardMove,
+        searched: &[BitBoardMove],
+        depth: usize,
+        ply: usize,
+    ) {
+        if self.killers[ply][0] != Some(m) {
+            self.killers[ply][1] = self.killers[ply][0];
+            self.killers[ply][0] = Some(m);
+        }
+        if let Some(previous) = ply.checked_sub(1).and_then(|ply| self.move_stack[ply]) {
+            self.countermoves[previous.get_from() as usize][previous.get_to() as usize] = Some(m);
+        }
+
+        let bonus = (depth * depth).min(400) as i32;
+        let history = &mut self.quiet_history[bitboard.active_color as usize];
+        update_history(
+            &mut history[m.get_from() as usize][m.get_to() as usize],
+            bonus,
+        );
+        for other in searched {
+            update_history(
+                &mut history[other.get_from() as usize][other.get_to() as usize],
+                -bonus,
+            );
+        }
+    }
 
-        for m in generate_moves(bitboard) {
-            let mut b = bitboard.clone();
-            b.apply_move(&m);
-            b.change_side();
-            let score = self.alpha_beta_min(&b, color, alpha, beta, depth - 1);
-            if score >= beta {
-                return beta;
+    /// Searches deeper and deeper until the limits run out and returns the best move found.
+    /// An iteration that gets interrupted still counts for the root moves it finished, as
+    /// the previous best move is always searched first. `None` means there is no legal move.
+    pub fn best_move(
+        &mut self,
+        bitboard: &BitBoardState,
+        history: &GameHistory,
+        limits: &SearchLimits,
+    ) -> Option<BitBoardMove> {
+        self.time_manager = TimeManager::new(limits, bitboard.active_color);
+        self.nodes = 0;
+        self.stopped = false;
+        self.previous_pv.clear();
+        self.history = history.clone();
+        self.killers = [[None; 2]; MAX_PLY];
+        *self.countermoves = [[None; 64]; 64];
+        *self.quiet_history = [[[0; 64]; 64]; 2];
+        self.transposition_table.new_search();
+
+        self.root_moves = generate_moves(bitboard)
+            .into_iter()
+            .filter(|m| limits.is_search_move(m))
+            .map(RootMove::new)
+            .collect();
+
+        let mut best_move = None;
+        let mut previous_value = None;
+        // The search makes and takes back moves on its own copy of the position
+        let mut board = bitboard.clone();
+
+        for depth in 1..=limits.max_depth() {
+            if self.root_moves.is_empty() {
+                break;
             }
-            if score > alpha {
-                alpha = score;
+            self.seldepth = 0;
+            self.root_depth = depth;
+            for root_move in &mut self.root_moves {
+                root_move.nodes = 0;
             }
+
+            // The window is widened on the side the score falls out of, until it holds the score
+            let mut delta = ASPIRATION_WINDOW;
+            let (mut alpha, mut beta) = match previous_value {
+                Some(value)
+                    if depth >= ASPIRATION_DEPTH && i64::abs(value) < MATE - MAX_PLY as i64 =>
+                {
+                    (value - delta, value + delta)
+                }
+                _ => (-INFINITY, INFINITY),
+            };
+            loop {
+                self.evaluate_moves(&mut board, depth, alpha, beta);
+                if self.stopped {
+                    break;
+                }
+
+                let lines = self.multi_pv.min(self.root_moves.len());
+                if self.root_moves[0].value >= beta {
+                    beta = (self.root_moves[0].value + delta).min(INFINITY);
+                } else if self.root_moves[lines - 1].value <= alpha {
+                    beta = (alpha + beta) / 2;
+                    alpha = (alpha - delta).max(-INFINITY);
+                } else {
+                    break;
+                }
+                delta *= 2;
+            }
+
+            // An interrupted iteration only counts if its best move beat the window, the
+            // previous best move is searched first so it is never worse than the last iteration
+            let best = &self.root_moves[0];
+            if best.value <= alpha {
+                break;
+            }
+            best_move = Some(best.m);
+            previous_value = Some(best.value);
+            self.previous_pv = best.pv.clone();
+
+            let lines: Vec<_> = self
+                .root_moves
+                .iter()
+                .take(self.multi_pv)
+                .take_while(|root_move| root_move.value > alpha)
+                .cloned()
+                .collect();
+            let time = self.time_manager.elapsed().as_millis() as u64;
+            let hashfull = self.transposition_table.hashfull();
+            for (i, root_move) in lines.into_iter().enumerate() {
+                self.report(SearchInfo {
+                    depth: Some(depth),
+                    seldepth: Some(self.seldepth),
+                    multipv: Some(i + 1),
+                    score: Some(Score::from_value(root_move.value)),
+                    nodes: Some(self.nodes),
+                    nps: Some(self.nodes * 1000 / time.max(1)),
+                    time: Some(time),
+                    hashfull: Some(hashfull),
+                    pv: root_move.pv,
+                    ..SearchInfo::default()
+                });
+            }
+
+            if self.stopped || self.time_manager.soft_limit_reached() {
+                break;
+            }
+        }
+
+        // Infinite and ponder searches may only report back once the GUI asks for it
+        while (limits.infinite || self.signals.is_pondering()) && !self.signals.is_stopped() {
+            std::thread::sleep(std::time::Duration::from_millis(1));
         }
-        alpha
+
+        best_move.or_else(|| {
+            generate_moves(bitboard)
+                .into_iter()
+                .find(|m| limits.is_search_move(m))
+        })
     }
 
-    pub fn alpha_beta_min(&mut self, bitboard: &BitBoardState, color: Color, alpha: i64, mut  beta: i64, depth: usize) -> i64 {
+    fn out_of_budget(&mut self) -> bool {
+        if self.time_manager.is_pondering() && !self.signals.is_pondering() {
+            self.time_manager.ponder_hit();
+        }
+
+        if !self.stopped
+            && (self.signals.is_stopped()
+                || self.time_manager.node_limit_reached(self.nodes)
+                || (self.nodes % TIME_CHECK_INTERVAL == 0
+                    && self.time_manager.hard_limit_reached()))
+        {
+            self.stopped = true;
+        }
+        self.stopped
+    }
+
+    /// Negamax principal variation search, scores are from the side to move's point of view.
+    /// Only the first move of a node is searched with the full window, the others get a null
+    /// window and are searched again only if they turn out better.
+    fn negamax(
+        &mut self,
+        bitboard: &mut BitBoardState,
+        mut alpha: i64,
+        beta: i64,
+        depth: usize,
+        ply: usize,
+    ) -> i64 {
         if depth == 0 {
-            let eval = -evaluate_bitboard(bitboard, color);
-            let table_len = self.transposition_table.len();
-            self.transposition_table[bitboard.zobrist_hash(self.zobrist_table) as usize % table_len] = eval;
-            return eval;
+            return self.quiescence(bitboard, alpha, beta, ply);
+        }
+
+        if self.out_of_budget() {
+            return 0;
+        }
+        self.nodes += 1;
+
+        self.seldepth = self.seldepth.max(ply);
+        self.pv.clear(ply);
+
+        if ply > 0
+            && (self.history.is_repetition(bitboard)
+                || bitboard.is_fifty_move_draw()
+                || bitboard.is_insufficient_material())
+        {
+            return 0;
         }
 
-        if self.transposition_table[bitboard.zobrist_hash(self.zobrist_table) as usize % self.transposition_table.len()] != 0 {
-            return self.transposition_table[bitboard.zobrist_hash(self.zobrist_table) as usize % self.transposition_table.len()]
+        let pv_node = beta - alpha > 1;
+        let key = bitboard.hash;
+        // The search leaving out a move does not share the hash entry of the full node
+        let excluded = self.excluded[ply];
+        let tt_entry = if excluded.is_none() {
+            self.transposition_table.probe(key)
+        } else {
+            None
+        };
+
+        // Lines of the principal variation are always searched to get their moves
+        if let Some(entry) = tt_entry {
+            if !pv_node && entry.depth >= depth {
+                let value = value_from_tt(entry.value, ply);
+                match entry.bound {
+                    Bound::Exact => return value,
+                    Bound::Lower if value >= beta => return value,
+                    Bound::Upper if value <= alpha => return value,
+                    _ => {}
+                }
+            }
+        }
+
+        // Away from the principal variation and out of check the static evaluation is trusted
+        // to tell nodes that are not worth a full search. Not while testing whether a move is
+        // singular, which needs the other moves searched.
+        let in_check = bitboard.in_check();
+        let prune = !pv_node && !in_check && excluded.is_none();
+        let static_eval = if in_check {
+            -INFINITY
+        } else {
+            evaluate_bitboard(bitboard, bitboard.active_color)
+        };
+
+        // Far enough above beta that no reply is going to bring it back
+        if prune
+            && self.pruning.reverse_futility
+            && depth <= REVERSE_FUTILITY_DEPTH
+            && beta.abs() < MATE - MAX_PLY as i64
+            && static_eval - REVERSE_FUTILITY_MARGIN * depth as i64 >= beta
+        {
+            return static_eval;
+        }
+
+        // Far enough below alpha that only captures could help
+        if prune
+            && self.pruning.razoring
+            && depth <= RAZORING_DEPTH
+            && static_eval + RAZORING_MARGIN * (depth as i64) <= alpha
+        {
+            let value = self.quiescence(bitboard, alpha, alpha + 1, ply);
+            if value <= alpha {
+                return value;
+            }
         }
 
-        for m in generate_moves(bitboard) {
-            let mut b = bitboard.clone();
-            b.apply_move(&m);
-            b.change_side();
-            let score = self.alpha_beta_max(&b, color, alpha, beta, depth - 1);
-            if score <= alpha {
+        // If passing still fails high a real move will too, searched shallower the further the
+        // evaluation is above beta. Never twice in a row, and not without pieces to move.
+        if prune
+            && self.pruning.null_move
+            && depth >= NULL_MOVE_DEPTH
+            && static_eval >= beta
+            && ply > 0
+            && self.move_stack[ply - 1].is_some()
+            && has_non_pawn_material(bitboard)
+        {
+            let reduction = 3 + depth / 4 + ((static_eval - beta) / 200).min(3) as usize;
+
+            self.history.push(bitboard);
+            self.move_stack[ply] = None;
+            self.extensions[ply + 1] = self.extensions[ply];
+            let undo = bitboard.make_null_move();
+            let value = -self.negamax(
+                bitboard,
+                -beta,
+                -beta + 1,
+                depth.saturating_sub(reduction + 1),
+                ply + 1,
+            );
+            bitboard.unmake_null_move(undo);
+            self.history.pop();
+
+            if self.stopped {
+                return 0;
+            }
+            if value >= beta {
+                // A mate found after passing is not proven
+                return if value >= MATE - MAX_PLY as i64 {
+                    beta
+                } else {
+                    value
+                };
+            }
+        }
+
+        let mut moves = generate_moves(bitboard);
+        if moves.is_empty() {
+            // Mates closer to the root score higher, so the shortest mate is preferred
+            return if in_check { -(MATE - ply as i64) } else { 0 };
+        }
+        if let Some(excluded) = excluded {
+            moves.retain(|m| m.to_u16() != excluded.to_u16());
+            // The excluded move was the only one, so it is as singular as a move gets
+            if moves.is_empty() {
                 return alpha;
             }
-            if score < beta {
-                beta = score;
+        }
+
+        // The hash move is singular if every other move fails low against a bound somewhat
+        // below its value, searched at reduced depth
+        let singular_move = tt_entry.and_then(|entry| {
+            let value = value_from_tt(entry.value, ply);
+            if ply > 0
+                && depth >= SINGULAR_DEPTH
+                && entry.depth + SINGULAR_TT_DEPTH >= depth
+                && entry.bound != Bound::Upper
+                && value.abs() < MATE - MAX_PLY as i64
+            {
+                entry.best_move.map(|m| (m, value))
+            } else {
+                None
+            }
+        });
+        let previous_move = ply.checked_sub(1).and_then(|ply| self.move_stack[ply]);
+
+        let hash_move = self
+            .pv_move(ply, &moves)
+            .or_else(|| tt_entry.and_then(|entry| entry.best_move));
+        let countermove = previous_move.and_then(|previous| {
+            self.countermoves[previous.get_from() as usize][previous.get_to() as usize]
+        });
+        let refutations = [self.killers[ply][0], self.killers[ply][1], countermove];
+        let mut picker = MovePicker::new(bitboard, moves, hash_move, refutations);
+
+        let original_alpha = alpha;
+        let mut best_value = -INFINITY;
+        let mut best_move = None;
+        let mut quiets_searched = Vec::new();
+        let mut moves_searched = 0;
+        while let Some(m) = picker.next(&self.quiet_history[bitboard.active_color as usize]) {
+            // Quiet moves late in the list of a node that is not mated are unlikely to matter
+            let quiet = is_quiet(&m);
+            let prunable =
+                prune && quiet && moves_searched > 0 && best_value > -(MATE - MAX_PLY as i64);
+            if prunable
+                && self.pruning.late_move_pruning
+                && depth <= LATE_MOVE_PRUNING_DEPTH
+                && quiets_searched.len() >= 3 + depth * depth
+            {
+                continue;
+            }
+
+            let gives_check = bitboard.gives_check(&m);
+
+            // A quiet move can not make up for a position far below alpha
+            if prunable
+                && !gives_check
+                && self.pruning.futility
+                && depth <= FUTILITY_DEPTH
+                && static_eval + FUTILITY_MARGIN * (depth as i64 + 1) <= alpha
+            {
+                continue;
+            }
+
+            let mut extension = 0;
+            match singular_move {
+                Some((singular, tt_value)) if singular.to_u16() == m.to_u16() => {
+                    let singular_beta = tt_value - 2 * depth as i64;
+                    let follow_pv = self.follow_pv;
+                    self.excluded[ply] = Some(m);
+                    let value = self.negamax(
+                        bitboard,
+                        singular_beta - 1,
+                        singular_beta,
+                        (depth - 1) / 2,
+                        ply,
+                    );
+                    self.excluded[ply] = None;
+                    self.follow_pv = follow_pv;
+                    self.pv.clear(ply);
+                    if self.stopped {
+                        return 0;
+                    }
+                    if value < singular_beta {
+                        extension = 1;
+                    }
+                }
+                _ => {}
+            }
+            // Checks, and on the principal variation recaptures, are seen through
+            let recapture = previous_move.is_some_and(|previous| {
+                previous.is_capture() && m.is_capture() && previous.get_to() == m.get_to()
+            });
+            if gives_check || (pv_node && recapture) {
+                extension = 1;
+            }
+            // The extensions of a line are bounded, so the tree stays finite
+            if self.extensions[ply] + extension > self.root_depth / 2 {
+                extension = 0;
+            }
+            self.extensions[ply + 1] = self.extensions[ply] + extension;
+            let new_depth = depth - 1 + extension;
+
+            self.history.push(bitboard);
+            self.move_stack[ply] = Some(m);
+            let undo = bitboard.make_move(&m);
+
+            let value = if moves_searched == 0 {
+                -self.negamax(bitboard, -beta, -alpha, new_depth, ply + 1)
+            } else {
+                // Later quiet moves are searched shallower first, and only searched again at
+                // full depth if they beat alpha
+                let mut reduction = 0;
+                if self.pruning.late_move_reductions
+                    && depth >= 3
+                    && quiet
+                    && !in_check
+                    && !gives_check
+                {
+                    reduction = self.reductions[depth.min(63)][moves_searched.min(63)] as usize;
+                    if pv_node || refutations.contains(&Some(m)) {
+                        reduction = reduction.saturating_sub(1);
+                    }
+                    reduction = reduction.min(depth - 2);
+                }
+
+                let mut value =
+                    -self.negamax(bitboard, -alpha - 1, -alpha, new_depth - reduction, ply + 1);
+                if reduction > 0 && value > alpha && !self.stopped {
+                    value = -self.negamax(bitboard, -alpha - 1, -alpha, new_depth, ply + 1);
+                }
+                if value > alpha && value < beta && !self.stopped {
+                    -self.negamax(bitboard, -beta, -alpha, new_depth, ply + 1)
+                } else {
+                    value
+                }
+            };
+            bitboard.unmake_move(&m, undo);
+            self.history.pop();
+            // Only the first move of a node can continue the previous line
+            self.follow_pv = false;
+
+            if self.stopped {
+                return 0;
+            }
+
+            if value > best_value {
+                best_value = value;
+                if value > alpha {
+                    alpha = value;
+                    best_move = Some(m);
+                    self.pv.update(ply, m);
+                    if alpha >= beta {
+                        if quiet {
+                            self.update_quiet_stats(bitboard, m, &quiets_searched, depth, ply);
+                        }
+                        break;
+                    }
+                }
             }
+            if quiet {
+                quiets_searched.push(m);
+            }
+            moves_searched += 1;
+        }
+
+        let bound = if best_value >= beta {
+            Bound::Lower
+        } else if best_value > original_alpha {
+            Bound::Exact
+        } else {
+            Bound::Upper
+        };
+        if excluded.is_none() {
+            self.transposition_table.store(
+                key,
+                best_move,
+                value_to_tt(best_value, ply),
+                depth,
+                bound,
+            );
         }
-        beta
+
+        best_value
     }
 
+    /// Resolves captures and promotions at the horizon so the position that gets evaluated is
+    /// quiet. The side to move may stand pat, unless it is in check and has to find an evasion.
+    fn quiescence(
+        &mut self,
+        bitboard: &mut BitBoardState,
+        mut alpha: i64,
+        beta: i64,
+        ply: usize,
+    ) -> i64 {
+        if self.out_of_budget() {
+            return 0;
+        }
+        self.nodes += 1;
+
+        self.seldepth = self.seldepth.max(ply);
+        self.pv.clear(ply);
+
+        if ply >= MAX_PLY - 1 {
+            return evaluate_bitboard(bitboard, bitboard.active_color);
+        }
 
-    fn evaluate_moves(&mut self, bitboard: &BitBoardState, depth: usize) -> BinaryHeap<MoveValue> {
-        let mut b = bitboard.clone();
-        let moves = generate_moves(&mut b);
-        moves.par_iter().map(|m| {
-            let mut b_c = b.clone();
-            b_c.apply_move(&m);
-            let value = self.alpha_beta_max(&b_c, b.active_color, i64::MIN, i64::MAX, depth);
-            MoveValue{ value, m: *m }
-        }).collect()
+        let in_check = bitboard.in_check();
+        let mut best_value = -INFINITY;
+
+        if !in_check {
+            let stand_pat = evaluate_bitboard(bitboard, bitboard.active_color);
+            if stand_pat >= beta {
+                return stand_pat;
+            }
+            alpha = alpha.max(stand_pat);
+            best_value = stand_pat;
+        }
+
+        let moves = if in_check {
+            generate_moves(bitboard)
+        } else {
+            generate_captures(bitboard)
+        };
+        if in_check && moves.is_empty() {
+            return -(MATE - ply as i64);
+        }
+
+        let mut picker = MovePicker::new(bitboard, moves, None, [None; 3]);
+        while let Some(m) = picker.next(&self.quiet_history[bitboard.active_color as usize]) {
+            // Captures that lose material are not going to raise alpha
+            if !in_check && !bitboard.see_ge(&m, 0) {
+                continue;
+            }
+
+            self.history.push(bitboard);
+            let undo = bitboard.make_move(&m);
+
+            let value = -self.quiescence(bitboard, -beta, -alpha, ply + 1);
+            bitboard.unmake_move(&m, undo);
+            self.history.pop();
+            if self.stopped {
+                return 0;
+            }
+
+            if value > best_value {
+                best_value = value;
+                if value > alpha {
+                    alpha = value;
+                    if alpha >= beta {
+                        break;
+                    }
+                }
+            }
+        }
+
+        best_value
     }
 
-}
+    /// Searches the root moves in the window `alpha`..`beta` and sorts them best first. The
+    /// best `multi_pv` moves inside the window get exact scores, the rest are only proven to be
+    /// worse than those and keep `-INFINITY`. A move that fails high ends the search, as the
+    /// window has to be widened anyway.
+    fn evaluate_moves(
+        &mut self,
+        bitboard: &mut BitBoardState,
+        depth: usize,
+        alpha: i64,
+        beta: i64,
+    ) {
+        // Values of the moves that beat the window so far, best first
+        let mut values = Vec::new();
 
-#[derive(Debug)]
-struct MoveValue {
-    value: i64,
-    m: BitBoardMove,
-}
+        for root_move in &mut self.root_moves {
+            root_move.value = -INFINITY;
+        }
+        self.follow_pv =
+            self.previous_pv.first().map(|m| m.to_u16()) == Some(self.root_moves[0].m.to_u16());
+        self.extensions[1] = 0;
+
+        for i in 0..self.root_moves.len() {
+            let m = self.root_moves[i].m;
+            if self.time_manager.elapsed().as_millis() as u64 >= CURRMOVE_DELAY {
+                self.report(SearchInfo {
+                    depth: Some(depth),
+                    currmove: Some(m),
+                    currmovenumber: Some(i + 1),
+                    ..SearchInfo::default()
+                });
+            }
+
+            let nodes = self.nodes;
+            self.history.push(bitboard);
+            self.move_stack[0] = Some(m);
+            let undo = bitboard.make_move(&m);
+
+            // A move has to beat the worst of the lines that are reported
+            let bound = values.get(self.multi_pv - 1).copied().unwrap_or(alpha);
+            let value = if values.len() < self.multi_pv {
+                -self.negamax(bitboard, -beta, -alpha, depth - 1, 1)
+            } else {
+                let value = -self.negamax(bitboard, -bound - 1, -bound, depth - 1, 1);
+                if value > bound && value < beta && !self.stopped {
+                    -self.negamax(bitboard, -beta, -bound, depth - 1, 1)
+                } else {
+                    value
+                }
+            };
+            bitboard.unmake_move(&m, undo);
+            self.history.pop();
+            self.follow_pv = false;
+
+            let root_move = &mut self.root_moves[i];
+            root_move.nodes += self.nodes - nodes;
+            if self.stopped {
+                break;
+            }
 
-impl PartialEq for MoveValue {
-    fn eq(&self, other: &Self) -> bool {
-        self.value.eq(&other.value)
+            if value > bound {
+                root_move.value = value;
+                root_move.pv = vec![m];
+                root_move.pv.extend_from_slice(self.pv.line(1));
+
+                let index = values.partition_point(|&v| v >= value);
+                values.insert(index, value);
+            }
+            if value >= beta {
+                break;
+            }
+        }
+
+        // Moves only known to be worse go by the effort it took to refute them
+        self.root_moves
+            .sort_by(|a, b| b.value.cmp(&a.value).then(b.nodes.cmp(&a.nodes)));
     }
 }
 
-impl PartialOrd for MoveValue {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        self.value.partial_cmp(&other.value)
+// Mate scores are stored relative to the position instead of the root, so they stay
+// correct when the position is reached again at another ply
+fn value_to_tt(value: i64, ply: usize) -> i64 {
+    if value >= MATE - MAX_PLY as i64 {
+        value + ply as i64
+    } else if value <= -(MATE - MAX_PLY as i64) {
+        value - ply as i64
+    } else {
+        value
     }
 }
 
-impl Eq for MoveValue {
+fn value_from_tt(value: i64, ply: usize) -> i64 {
+    if value >= MATE - MAX_PLY as i64 {
+        value - ply as i64
+    } else if value <= -(MATE - MAX_PLY as i64) {
+        value + ply as i64
+    } else {
+        value
+    }
+}
 
+/// A legal move of the root position and what the search found out about it
+#[derive(Clone, Debug)]
+struct RootMove {
+    m: BitBoardMove,
+    /// Score in the window of the last search, `-INFINITY` if it did not beat the window
+    value: i64,
+    /// Nodes spent on the move in the current iteration
+    nodes: u64,
+    /// The move followed by the best line found after it
+    pv: Vec<BitBoardMove>,
 }
 
-impl Ord for MoveValue {
-    fn cmp(&self, other: &Self) -> Ordering {
-        self.value.cmp(&other.value)
+impl RootMove {
+    fn new(m: BitBoardMove) -> Self {
+        Self {
+            m,
+            value: -INFINITY,
+            nodes: 0,
+            pv: vec![m],
+        }
     }
 }
 
-
 #[cfg(test)]
 mod tests {
-    use crate::bitboard::{BitBoardState, generate_moves};
-    use crate::evaluation::evaluate_bitboard;
-    use crate::search::{best_move, MoveValue};
-    use std::collections::BinaryHeap;
+    use crate::bitboard::{generate_moves, BitBoardMove, BitBoardState};
+    use crate::history::GameHistory;
+    use crate::search::{
+        MovePicker, Pruning, PvTable, Score, SearchDriver, SearchInfo, INFINITY, MATE,
+    };
+    use crate::time_manager::SearchLimits;
+    use std::sync::{Arc, Mutex};
 
     #[test]
     fn test_best_move() {
-        let mut board =
+        let board =
             BitBoardState::from_fen("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1")
                 .unwrap();
 
-        let b = best_move(&board, 0);
+        let limits = SearchLimits {
+            depth: Some(2),
+            ..SearchLimits::default()
+        };
+        let b = SearchDriver::new(16).best_move(&board, &GameHistory::new(), &limits);
         println!("{:?}", b);
+        assert!(b.is_some());
+    }
+
+    #[test]
+    fn test_info() {
+        let board = BitBoardState::new();
+        let limits = SearchLimits {
+            depth: Some(3),
+            ..SearchLimits::default()
+        };
+
+        let infos = Arc::new(Mutex::new(Vec::new()));
+        let mut search_driver = SearchDriver::new(16);
+        search_driver.set_info_callback({
+            let infos = Arc::clone(&infos);
+            move |info: &SearchInfo| infos.lock().unwrap().push(info.clone())
+        });
+        let b = search_driver.best_move(&board, &GameHistory::new(), &limits);
+
+        let infos = infos.lock().unwrap();
+        assert_eq!(infos.len(), 3);
+        for (depth, info) in (1..=3).zip(infos.iter()) {
+            assert_eq!(info.depth, Some(depth));
+            assert!(matches!(info.score, Some(Score::Cp(_))));
+            assert!(info.nodes.unwrap() > 0);
+        }
+        assert_eq!(infos[2].pv.first(), b.as_ref());
+    }
+
+    #[test]
+    fn test_multi_pv() {
+        let board = BitBoardState::new();
+        let limits = SearchLimits {
+            depth: Some(3),
+            ..SearchLimits::default()
+        };
+
+        let infos = Arc::new(Mutex::new(Vec::new()));
+        let mut search_driver = SearchDriver::new(16);
+        search_driver.set_multi_pv(3);
+        search_driver.set_info_callback({
+            let infos = Arc::clone(&infos);
+            move |info: &SearchInfo| infos.lock().unwrap().push(info.clone())
+        });
+        search_driver.best_move(&board, &GameHistory::new(), &limits);
+
+        let infos = infos.lock().unwrap();
+        let last: Vec<_> = infos.iter().filter(|info| info.depth == Some(3)).collect();
+        assert_eq!(last.len(), 3);
+        for (k, info) in last.iter().enumerate() {
+            assert_eq!(info.multipv, Some(k + 1));
+            assert!(!info.pv.is_empty());
+        }
+        assert_ne!(last[0].pv[0], last[1].pv[0]);
+        assert_ne!(last[1].pv[0], last[2].pv[0]);
+
+        let value = |info: &SearchInfo| match info.score {
+            Some(Score::Cp(cp)) => cp,
+            _ => panic!("expected a centipawn score"),
+        };
+        assert!(value(last[0]) >= value(last[1]));
+        assert!(value(last[1]) >= value(last[2]));
+    }
+
+    #[test]
+    fn test_pv_table() {
+        let e2e4 = BitBoardMove::from_long_algebraic(b"e2e4").unwrap();
+        let e7e5 = BitBoardMove::from_long_algebraic(b"e7e5").unwrap();
+        let g1f3 = BitBoardMove::from_long_algebraic(b"g1f3").unwrap();
+
+        let mut pv = PvTable::new();
+        pv.clear(3);
+        pv.update(2, g1f3);
+        pv.update(1, e7e5);
+        pv.update(0, e2e4);
+        assert_eq!(pv.line(0), &[e2e4, e7e5, g1f3]);
+        assert_eq!(pv.line(1), &[e7e5, g1f3]);
+
+        // A new best move at ply 1 whose child is a leaf cuts the line short
+        pv.clear(2);
+        pv.update(1, g1f3);
+        assert_eq!(pv.line(1), &[g1f3]);
+    }
+
+    #[test]
+    fn test_pruning() {
+        let board = BitBoardState::from_fen(
+            "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+        )
+        .unwrap();
+        let limits = SearchLimits {
+            depth: Some(4),
+            ..SearchLimits::default()
+        };
+
+        let mut full = SearchDriver::new(16);
+        full.set_pruning(Pruning {
+            null_move: false,
+            late_move_reductions: false,
+            reverse_futility: false,
+            futility: false,
+            razoring: false,
+            late_move_pruning: false,
+        });
+        assert!(full
+            .best_move(&board, &GameHistory::new(), &limits)
+            .is_some());
+
+        let mut selective = SearchDriver::new(16);
+        assert!(selective
+            .best_move(&board, &GameHistory::new(), &limits)
+            .is_some());
+        assert!(selective.nodes() < full.nodes());
+    }
+
+    #[test]
+    fn test_move_picker() {
+        // Taking the pawn on d5 with the queen loses it
+        let board = BitBoardState::from_fen("4k3/8/4p3/3p4/2P5/8/8/3QK3 w - - 0 1").unwrap();
+        let parse = |m| board.parse_uci_move(m).unwrap();
+        let moves = generate_moves(&board);
+        let count = moves.len();
+
+        let mut quiet_history = [[0; 64]; 64];
+        quiet_history[4][12] = 100;
+        let mut picker = MovePicker::new(
+            &board,
+            moves,
+            Some(parse("d1a4")),
+            [Some(parse("d1b3")), None, Some(parse("d1a4"))],
+        );
+        let mut order = Vec::new();
+        while let Some(m) = picker.next(&quiet_history) {
+            order.push(m);
+        }
+
+        assert_eq!(order.len(), count);
+        assert_eq!(order[0], parse("d1a4"));
+        assert_eq!(order[1], parse("c4d5"));
+        assert_eq!(order[2], parse("d1b3"));
+        assert_eq!(order[3], parse("e1e2"));
+        assert_eq!(order[count - 1], parse("d1d5"));
+    }
+
+    #[test]
+    fn test_mate() {
+        let limits = SearchLimits {
+            depth: Some(3),
+            ..SearchLimits::default()
+        };
+
+        let board = BitBoardState::from_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1").unwrap();
+        let infos = Arc::new(Mutex::new(Vec::new()));
+        let mut search_driver = SearchDriver::new(16);
+        search_driver.set_info_callback({
+            let infos = Arc::clone(&infos);
+            move |info: &SearchInfo| infos.lock().unwrap().push(info.clone())
+        });
+        let b = search_driver.best_move(&board, &GameHistory::new(), &limits);
+
+        assert_eq!(b, Some(BitBoardMove::from_long_algebraic(b"a1a8").unwrap()));
+        assert_eq!(
+            infos.lock().unwrap().last().unwrap().score,
+            Some(Score::Mate(1))
+        );
+
+        // Checkmate and stalemate both leave no moves, but only one of them is lost
+        let mut mated = BitBoardState::from_fen("R5k1/5ppp/8/8/8/8/8/6K1 b - - 0 1").unwrap();
+        let mut stalemate = BitBoardState::from_fen("k7/2Q5/8/8/8/8/8/K7 b - - 0 1").unwrap();
+        let mut search_driver = SearchDriver::new(16);
+        assert_eq!(
+            search_driver.negamax(&mut mated, -INFINITY, INFINITY, 2, 0),
+            -MATE
+        );
+        assert_eq!(
+            search_driver.negamax(&mut stalemate, -INFINITY, INFINITY, 2, 0),
+            0
+        );
+    }
+
+    #[test]
+    fn test_check_extension() {
+        // Rh6+, Rg7+ and Rh8# take five plies, the checks extend a search of four to see them.
+        // Without pruning, which is free to cut such a line short at this depth.
+        let board = BitBoardState::from_fen("8/8/1k6/6R1/8/8/8/K6R w - - 0 1").unwrap();
+        let limits = SearchLimits {
+            depth: Some(4),
+            ..SearchLimits::default()
+        };
+        let infos = Arc::new(Mutex::new(Vec::new()));
+        let mut search_driver = SearchDriver::new(16);
+        search_driver.set_pruning(Pruning {
+            null_move: false,
+            late_move_reductions: false,
+            reverse_futility: false,
+            futility: false,
+            razoring: false,
+            late_move_pruning: false,
+        });
+        search_driver.set_info_callback({
+            let infos = Arc::clone(&infos);
+            move |info: &SearchInfo| infos.lock().unwrap().push(info.clone())
+        });
+        search_driver.best_move(&board, &GameHistory::new(), &limits);
+
+        assert_eq!(
+            infos.lock().unwrap().last().unwrap().score,
+            Some(Score::Mate(3))
+        );
+    }
+
+    #[test]
+    fn test_aspiration() {
+        // The mate is only found once the score has been around a rook up for a few iterations,
+        // so the window has to be widened all the way
+        let board = BitBoardState::from_fen("8/8/1k6/6R1/8/8/8/K6R w - - 0 1").unwrap();
+        let limits = SearchLimits {
+            depth: Some(7),
+            ..SearchLimits::default()
+        };
+        let infos = Arc::new(Mutex::new(Vec::new()));
+        let mut search_driver = SearchDriver::new(16);
+        search_driver.set_info_callback({
+            let infos = Arc::clone(&infos);
+            move |info: &SearchInfo| infos.lock().unwrap().push(info.clone())
+        });
+        let b = search_driver.best_move(&board, &GameHistory::new(), &limits);
+
+        let infos = infos.lock().unwrap();
+        assert!(infos
+            .iter()
+            .any(|info| matches!(info.score, Some(Score::Cp(value)) if value > 0)));
+        assert_eq!(infos.last().unwrap().score, Some(Score::Mate(3)));
+
+        // The root moves are sorted best first and every one was searched by the last iteration
+        let root_moves = &search_driver.root_moves;
+        assert_eq!(root_moves.len(), generate_moves(&board).len());
+        assert_eq!(Some(root_moves[0].m), b);
+        assert_eq!(root_moves[0].pv, infos.last().unwrap().pv);
+        assert!(root_moves.windows(2).all(|w| w[0].value >= w[1].value));
+        assert!(root_moves.iter().all(|root_move| root_move.nodes > 0));
+    }
+
+    #[test]
+    fn test_draws() {
+        let mut search_driver = SearchDriver::new(16);
+
+        // A queen up, but the position was seen before or the fifty moves are up
+        let mut board = BitBoardState::from_fen("4k3/8/8/8/8/8/8/3QK3 w - - 4 40").unwrap();
+        assert!(search_driver.negamax(&mut board, -INFINITY, INFINITY, 2, 1) > 0);
+        let mut history = GameHistory::new();
+        for m in &["d1d2", "e8f8", "d2d1", "f8e8"] {
+            let m = board.parse_uci_move(m).unwrap();
+            history.push(&board);
+            board.make_move(&m);
+        }
+        search_driver.history = history;
+        assert_eq!(
+            search_driver.negamax(&mut board, -INFINITY, INFINITY, 2, 1),
+            0
+        );
+
+        let mut board = BitBoardState::from_fen("4k3/8/8/8/8/8/8/3QK3 w - - 100 90").unwrap();
+        search_driver.history = GameHistory::new();
+        assert_eq!(
+            search_driver.negamax(&mut board, -INFINITY, INFINITY, 2, 1),
+            0
+        );
+
+        let mut board = BitBoardState::from_fen("4k3/8/8/8/8/8/8/2B1K3 w - - 0 1").unwrap();
+        assert_eq!(
+            search_driver.negamax(&mut board, -INFINITY, INFINITY, 2, 1),
+            0
+        );
+    }
+
+    #[test]
+    fn test_quiescence() {
+        // The pawn on d5 is defended, taking it loses the queen one ply past the horizon
+        let board = BitBoardState::from_fen("4k3/8/4p3/3p4/8/8/8/3QK3 w - - 0 1").unwrap();
+        let limits = SearchLimits {
+            depth: Some(1),
+            ..SearchLimits::default()
+        };
+        let b = SearchDriver::new(16).best_move(&board, &GameHistory::new(), &limits);
+
+        assert!(b.is_some());
+        assert_ne!(b, Some(BitBoardMove::from_long_algebraic(b"d1d5").unwrap()));
+    }
+
+    #[test]
+    fn test_score() {
+        assert_eq!(Score::from_value(35), Score::Cp(35));
+        assert_eq!(Score::from_value(MATE - 1), Score::Mate(1));
+        assert_eq!(Score::from_value(MATE - 3), Score::Mate(2));
+        assert_eq!(Score::from_value(-(MATE - 2)), Score::Mate(-1));
+    }
+
+    #[test]
+    fn test_node_limit() {
+        let board = BitBoardState::new();
+
+        let limits = SearchLimits {
+            nodes: Some(1_000),
+            ..SearchLimits::default()
+        };
+        let mut search_driver = SearchDriver::new(16);
+        let b = search_driver.best_move(&board, &GameHistory::new(), &limits);
+
+        assert!(b.is_some());
+        assert!(search_driver.nodes() <= 1_000);
     }
 }
@@ -0,0 +1,228 @@
+use crate::bitboard::BitBoardMove;
+use crate::board::Color;
+use std::time::{Duration, Instant};
+
+// Depth used for a bare `go` with no other limits
+pub const DEFAULT_DEPTH: usize = 6;
+pub const MAX_DEPTH: usize = 64;
+
+// Time kept in reserve for GUI and OS latency on every move
+const MOVE_OVERHEAD: Duration = Duration::from_millis(30);
+// Assumed number of moves left when the GUI does not send movestogo
+const DEFAULT_MOVES_TO_GO: u32 = 30;
+
+/// The limits a GUI attaches to a `go` command. All times are in milliseconds.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SearchLimits {
+    pub search_moves: Vec<BitBoardMove>,
+    pub ponder: bool,
+    pub wtime: Option<u64>,
+    pub btime: Option<u64>,
+    pub winc: Option<u64>,
+    pub binc: Option<u64>,
+    pub moves_to_go: Option<u32>,
+    pub depth: Option<usize>,
+    pub nodes: Option<u64>,
+    pub mate: Option<usize>,
+    pub move_time: Option<u64>,
+    pub infinite: bool,
+}
+
+impl SearchLimits {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether the root move `m` may be searched. Moves are compared with their flags, so a
+    /// promotion only matches the piece that was asked for.
+    pub fn is_search_move(&self, m: &BitBoardMove) -> bool {
+        self.search_moves.is_empty()
+            || self
+                .search_moves
+                .iter()
+                .any(|search_move| search_move.to_u16() == m.to_u16())
+    }
+
+    pub fn is_timed(&self) -> bool {
+        self.wtime.is_some() || self.btime.is_some() || self.move_time.is_some()
+    }
+
+    /// The deepest iteration the search is allowed to start
+    pub fn max_depth(&self) -> usize {
+        if let Some(depth) = self.depth {
+            return depth.clamp(1, MAX_DEPTH);
+        }
+
+        if let Some(mate) = self.mate {
+            return (2 * mate).saturating_sub(1).clamp(1, MAX_DEPTH);
+        }
+
+        if self.infinite || self.is_timed() || self.nodes.is_some() {
+            MAX_DEPTH
+        } else {
+            DEFAULT_DEPTH
+        }
+    }
+}
+
+/// Turns the clock state of a `go` command into a soft and a hard budget.
+///
+/// The soft budget decides whether another iteration is worth starting, the hard
+/// budget aborts a search that is already running. While pondering neither budget
+/// applies, the clock only starts on `ponderhit`.
+#[derive(Clone, Debug)]
+pub struct TimeManager {
+    start: Instant,
+    soft_limit: Option<Duration>,
+    hard_limit: Option<Duration>,
+    node_limit: Option<u64>,
+    pondering: bool,
+}
+
+impl TimeManager {
+    pub fn new(limits: &SearchLimits, color: Color) -> Self {
+        let (time, increment) = match color {
+            Color::White => (limits.wtime, limits.winc),
+            Color::Black => (limits.btime, limits.binc),
+        };
+
+        let (soft_limit, hard_limit) = if limits.infinite {
+            (None, None)
+        } else if let Some(move_time) = limits.move_time {
+            let budget = Duration::from_millis(move_time)
+                .saturating_sub(MOVE_OVERHEAD)
+                .max(Duration::from_millis(1));
+            (Some(budget), Some(budget))
+        } else if let Some(time) = time {
+            let available = Duration::from_millis(time)
+                .saturating_sub(MOVE_OVERHEAD)
+                .max(Duration::from_millis(1));
+            let increment = Duration::from_millis(increment.unwrap_or(0));
+            let moves_to_go = limits.moves_to_go.unwrap_or(DEFAULT_MOVES_TO_GO).max(1);
+
+            // Never plan to spend more than 80% of the clock on a single move
+            let maximum = available * 4 / 5;
+            let soft = (available / moves_to_go + increment * 3 / 4).min(maximum);
+            let hard = (soft * 4).min(maximum);
+            (Some(soft), Some(hard))
+        } else {
+            (None, None)
+        };
+
+        Self {
+            start: Instant::now(),
+            soft_limit,
+            hard_limit,
+            node_limit: limits.nodes,
+            pondering: limits.ponder,
+        }
+    }
+
+    pub fn is_pondering(&self) -> bool {
+        self.pondering
+    }
+
+    /// The opponent played the expected move, so the budget starts counting from now
+    pub fn ponder_hit(&mut self) {
+        self.pondering = false;
+        self.start = Instant::now();
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    pub fn soft_limit(&self) -> Option<Duration> {
+        self.soft_limit
+    }
+
+    pub fn hard_limit(&self) -> Option<Duration> {
+        self.hard_limit
+    }
+
+    pub fn node_limit_reached(&self, nodes: u64) -> bool {
+        matches!(self.node_limit, Some(limit) if nodes >= limit)
+    }
+
+    /// True once a new iteration should no longer be started
+    pub fn soft_limit_reached(&self) -> bool {
+        !self.pondering && matches!(self.soft_limit, Some(limit) if self.elapsed() >= limit)
+    }
+
+    /// True once the running search has to be aborted
+    pub fn hard_limit_reached(&self) -> bool {
+        !self.pondering && matches!(self.hard_limit, Some(limit) if self.elapsed() >= limit)
+    }
+}
+
+impl Default for TimeManager {
+    fn default() -> Self {
+        Self::new(&SearchLimits::default(), Color::White)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::board::Color;
+    use crate::time_manager::{SearchLimits, TimeManager, DEFAULT_DEPTH, MAX_DEPTH};
+    use std::time::Duration;
+
+    #[test]
+    fn test_max_depth() {
+        assert_eq!(SearchLimits::new().max_depth(), DEFAULT_DEPTH);
+
+        let limits = SearchLimits {
+            depth: Some(3),
+            ..SearchLimits::default()
+        };
+        assert_eq!(limits.max_depth(), 3);
+
+        let limits = SearchLimits {
+            mate: Some(2),
+            ..SearchLimits::default()
+        };
+        assert_eq!(limits.max_depth(), 3);
+
+        let limits = SearchLimits {
+            wtime: Some(1000),
+            ..SearchLimits::default()
+        };
+        assert_eq!(limits.max_depth(), MAX_DEPTH);
+    }
+
+    #[test]
+    fn test_move_time() {
+        let limits = SearchLimits {
+            move_time: Some(1000),
+            ..SearchLimits::default()
+        };
+        let time_manager = TimeManager::new(&limits, Color::White);
+
+        assert_eq!(time_manager.soft_limit(), Some(Duration::from_millis(970)));
+        assert_eq!(time_manager.hard_limit(), Some(Duration::from_millis(970)));
+    }
+
+    #[test]
+    fn test_clock() {
+        let limits = SearchLimits {
+            wtime: Some(60_000),
+            btime: Some(1_000),
+            winc: Some(1_000),
+            binc: Some(0),
+            ..SearchLimits::default()
+        };
+
+        let white = TimeManager::new(&limits, Color::White);
+        let black = TimeManager::new(&limits, Color::Black);
+
+        let white_soft = white.soft_limit().unwrap();
+        let white_hard = white.hard_limit().unwrap();
+        assert!(white_soft > Duration::from_millis(1_000));
+        assert!(white_soft < white_hard);
+        assert!(white_hard < Duration::from_millis(60_000));
+
+        // The side short on time must never be given more than it has
+        assert!(black.hard_limit().unwrap() < Duration::from_millis(1_000));
+        assert!(black.soft_limit().unwrap() < white_soft);
+    }
+}
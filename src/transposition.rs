@@ -0,0 +1,289 @@
+use crate::bitboard::BitBoardMove;
+use std::mem::size_of;
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+
+const ENTRIES_PER_BUCKET: usize = 4;
+// Ages are kept in six bits
+const AGE_MASK: u8 = 0x3f;
+/// The largest table the Hash option accepts, in megabytes
+pub const MAX_MEGABYTES: usize = 65536;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Bound {
+    /// The value is exact
+    Exact = 1,
+    /// The search failed high, the real value is at least this
+    Lower = 2,
+    /// The search failed low, the real value is at most this
+    Upper = 3,
+}
+
+/// What is known about a position from an earlier search
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TtEntry {
+    pub best_move: Option<BitBoardMove>,
+    pub value: i64,
+    pub depth: usize,
+    pub bound: Bound,
+    age: u8,
+}
+
+impl TtEntry {
+    // move: 16 bits, depth: 8 bits, bound: 2 bits, age: 6 bits, value: 32 bits
+    fn pack(&self) -> u64 {
+        let m = self.best_move.map_or(0, |m| m.to_u16()) as u64;
+        m | (self.depth.min(u8::MAX as usize) as u64) << 16
+            | (self.bound as u64) << 24
+            | ((self.age & AGE_MASK) as u64) << 26
+            | (self.value as i32 as u32 as u64) << 32
+    }
+
+    fn unpack(data: u64) -> Option<Self> {
+        let bound = match (data >> 24) & 0b11 {
+            1 => Bound::Exact,
+            2 => Bound::Lower,
+            3 => Bound::Upper,
+            _ => return None,
+        };
+
+        let m = data as u16;
+        Some(Self {
+            best_move: if m == 0 {
+                None
+            } else {
+                Some(BitBoardMove::from_u16(m))
+            },
+            value: (data >> 32) as u32 as i32 as i64,
+            depth: ((data >> 16) & 0xff) as usize,
+            bound,
+            age: ((data >> 26) as u8) & AGE_MASK,
+        })
+    }
+}
+
+/// The key is stored xored with the data, so an entry torn by two threads writing at the
+/// same time no longer matches its key and is ignored instead of returning garbage.
+#[derive(Debug, Default)]
+struct Entry {
+    key: AtomicU64,
+    data: AtomicU64,
+}
+
+impl Entry {
+    fn load(&self) -> (u64, u64) {
+        let data = self.data.load(Ordering::Relaxed);
+        (self.key.load(Ordering::Relaxed) ^ data, data)
+    }
+
+    fn store(&self, key: u64, data: u64) {
+        self.key.store(key ^ data, Ordering::Relaxed);
+        self.data.store(data, Ordering::Relaxed);
+    }
+}
+
+#[derive(Debug, Default)]
+#[repr(align(64))]
+struct Bucket {
+    entries: [Entry; ENTRIES_PER_BUCKET],
+}
+
+/// A hash table of earlier search results that any number of search threads can share
+#[derive(Debug)]
+pub struct TranspositionTable {
+    buckets: Vec<Bucket>,
+    age: AtomicU8,
+}
+
+impl TranspositionTable {
+    /// Creates a table that uses about `megabytes` of memory
+    pub fn new(megabytes: usize) -> Self {
+        let buckets = (megabytes * 1024 * 1024 / size_of::<Bucket>()).max(1);
+
+        Self {
+            buckets: (0..buckets).map(|_| Bucket::default()).collect(),
+            age: AtomicU8::new(0),
+        }
+    }
+
+    pub fn megabytes(&self) -> usize {
+        self.buckets.len() * size_of::<Bucket>() / (1024 * 1024)
+    }
+
+    pub fn clear(&self) {
+        for entry in self.buckets.iter().flat_map(|bucket| bucket.entries.iter()) {
+            entry.store(0, 0);
+        }
+        self.age.store(0, Ordering::Relaxed);
+    }
+
+    /// Marks the entries of earlier searches as old, so they are replaced first
+    pub fn new_search(&self) {
+        let age = self.age.load(Ordering::Relaxed);
+        self.age.store((age + 1) & AGE_MASK, Ordering::Relaxed);
+    }
+
+    fn bucket(&self, key: u64) -> &Bucket {
+        &self.buckets[(key % self.buckets.len() as u64) as usize]
+    }
+
+    pub fn probe(&self, key: u64) -> Option<TtEntry> {
+        self.bucket(key)
+            .entries
+            .iter()
+            .map(Entry::load)
+            .find(|&(entry_key, data)| entry_key == key && data != 0)
+            .and_then(|(_, data)| TtEntry::unpack(data))
+    }
+
+    pub fn store(
+        &self,
+        key: u64,
+        best_move: Option<BitBoardMove>,
+        value: i64,
+        depth: usize,
+        bound: Bound,
+    ) {
+        let age = self.age.load(Ordering::Relaxed);
+        let bucket = self.bucket(key);
+
+        // Reuse the entry of the same position, otherwise replace the least useful entry,
+        // which is the one that is oldest and was searched the least deep
+        let slot = bucket
+            .entries
+            .iter()
+            .find(|entry| entry.load().0 == key)
+            .unwrap_or_else(|| {
+                bucket
+                    .entries
+                    .iter()
+                    .min_by_key(|entry| match TtEntry::unpack(entry.load().1) {
+                        Some(old) => {
+                            let age_difference = age.wrapping_sub(old.age) & AGE_MASK;
+                            old.depth as i64 - 8 * age_difference as i64
+                        }
+                        None => i64::MIN,
+                    })
+                    .unwrap()
+            });
+
+        // Keep the move of an earlier search of this position if this one did not find one
+        let best_move = best_move.or_else(|| {
+            let (entry_key, data) = slot.load();
+            if entry_key == key {
+                TtEntry::unpack(data).and_then(|old| old.best_move)
+            } else {
+                None
+            }
+        });
+
+        let entry = TtEntry {
+            best_move,
+            value,
+            depth,
+            bound,
+            age,
+        };
+        slot.store(key, entry.pack());
+    }
+
+    /// Permille of the first entries that were written during the current search
+    pub fn hashfull(&self) -> usize {
+        let age = self.age.load(Ordering::Relaxed);
+        let sample = self
+            .buckets
+            .iter()
+            .flat_map(|bucket| bucket.entries.iter())
+            .take(1000);
+
+        let mut total = 0;
+        let mut used = 0;
+        for entry in sample {
+            total += 1;
+            if matches!(TtEntry::unpack(entry.load().1), Some(e) if e.age == age) {
+                used += 1;
+            }
+        }
+        used * 1000 / total
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::bitboard::BitBoardMove;
+    use crate::transposition::{Bound, TranspositionTable};
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn test_store_probe() {
+        let table = TranspositionTable::new(1);
+        let m = BitBoardMove::from_long_algebraic(b"e7e8q").unwrap();
+
+        assert_eq!(table.probe(42), None);
+        table.store(42, Some(m), -1234, 7, Bound::Lower);
+
+        let entry = table.probe(42).unwrap();
+        assert_eq!(
+            entry.best_move.unwrap().to_long_algebraic().unwrap(),
+            "e7e8q"
+        );
+        assert_eq!(entry.value, -1234);
+        assert_eq!(entry.depth, 7);
+        assert_eq!(entry.bound, Bound::Lower);
+
+        // Same bucket, different position
+        let other = 42 + table.buckets.len() as u64;
+        assert_eq!(table.probe(other), None);
+
+        table.store(42, None, 5, 8, Bound::Exact);
+        assert_eq!(table.probe(42).unwrap().best_move, Some(m));
+
+        table.clear();
+        assert_eq!(table.probe(42), None);
+    }
+
+    #[test]
+    fn test_replacement() {
+        let table = TranspositionTable::new(1);
+        let buckets = table.buckets.len() as u64;
+
+        for i in 0..4 {
+            table.store(1 + i * buckets, None, 0, 10, Bound::Exact);
+        }
+        table.new_search();
+        table.store(1 + 4 * buckets, None, 0, 1, Bound::Exact);
+
+        // The bucket was full, so one of the entries of the older search made room
+        assert!(table.probe(1 + 4 * buckets).is_some());
+        assert_eq!(
+            (0..4)
+                .filter(|i| table.probe(1 + i * buckets).is_some())
+                .count(),
+            3
+        );
+    }
+
+    #[test]
+    fn test_concurrent_access() {
+        let table = Arc::new(TranspositionTable::new(1));
+
+        let threads: Vec<_> = (0..4)
+            .map(|t| {
+                let table = Arc::clone(&table);
+                thread::spawn(move || {
+                    for key in 1..10_000u64 {
+                        table.store(key, None, (key * t) as i64, t as usize, Bound::Exact);
+                        if let Some(entry) = table.probe(key) {
+                            assert_eq!(entry.value, (key * entry.depth as u64) as i64);
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        assert!(table.hashfull() > 0);
+    }
+}
@@ -1,14 +1,13 @@
-use std::collections::HashMap;
-
-use crate::bitboard::{
-    perft_report, BitBoard, BitBoardMove, BitBoardState, BISHOP_PROMOTION,
-    BISHOP_PROMOTION_CAPTURE, KNIGHT_PROMOTION, KNIGHT_PROMOTION_CAPTURE, QUEEN_PROMOTION,
-    QUEEN_PROMOTION_CAPTURE, ROOK_PROMOTION, ROOK_PROMOTION_CAPTURE
-};
-use crate::interface::index_to_algebraic;
+use crate::bitboard::{perft_report, BitBoardMove, BitBoardState};
+use crate::history::GameHistory;
+use crate::search::{Pruning, Score, SearchDriver, SearchInfo, SearchSignals};
+use crate::time_manager::SearchLimits;
+use crate::transposition::MAX_MEGABYTES;
 use crate::{APPLICATION_AUTHOR, APPLICATION_NAME, APPLICATION_VERSION};
-use std::str::from_utf8;
-use crate::search::{SearchDriver};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread::{self, JoinHandle};
+use tokio::sync::mpsc::UnboundedSender;
 
 pub enum ResponseType {
     Response(String),
@@ -20,7 +19,9 @@ pub enum ResponseType {
 
 pub struct Options {
     hash: usize,
+    multi_pv: usize,
     log_file: Option<String>,
+    pruning: Pruning,
 }
 
 impl Options {
@@ -29,26 +30,54 @@ impl Options {
     }
 
     fn get_options(&self) -> String {
-        String::from(
-            "option name Hash type spin default 16 min 1 max 33554432\n\
-             option name LogFile type string default \n",
+        format!(
+            "option name Hash type spin default 16 min 1 max {}\n\
+             option name MultiPV type spin default 1 min 1 max 256\n\
+             option name LogFile type string default \n\
+             option name NullMove type check default true\n\
+             option name LateMoveReductions type check default true\n\
+             option name ReverseFutility type check default true\n\
+             option name Futility type check default true\n\
+             option name Razoring type check default true\n\
+             option name LateMovePruning type check default true\n",
+            MAX_MEGABYTES
         )
     }
 
     fn set_option<S: AsRef<str>>(&mut self, option: S, value: S) {
         match option.as_ref().trim().to_lowercase().as_str() {
             "hash" => {
-                self.hash = value.as_ref().parse().unwrap();
+                if let Ok(hash) = value.as_ref().trim().parse::<usize>() {
+                    self.hash = hash.clamp(1, MAX_MEGABYTES);
+                }
+            }
+            "multipv" => {
+                if let Ok(multi_pv) = value.as_ref().trim().parse::<usize>() {
+                    self.multi_pv = multi_pv.clamp(1, 256);
+                }
             }
             "logfile" => {
                 let file = value.as_ref().trim();
-                self.log_file = if file.len() == 0 {
+                self.log_file = if file.is_empty() {
                     None
                 } else {
                     Some(String::from(file))
                 };
             }
-            _ => {}
+            option => {
+                let pruning = match option {
+                    "nullmove" => &mut self.pruning.null_move,
+                    "latemovereductions" => &mut self.pruning.late_move_reductions,
+                    "reversefutility" => &mut self.pruning.reverse_futility,
+                    "futility" => &mut self.pruning.futility,
+                    "razoring" => &mut self.pruning.razoring,
+                    "latemovepruning" => &mut self.pruning.late_move_pruning,
+                    _ => return,
+                };
+                if let Ok(enabled) = value.as_ref().trim().parse::<bool>() {
+                    *pruning = enabled;
+                }
+            }
         }
     }
 }
@@ -57,7 +86,9 @@ impl Default for Options {
     fn default() -> Self {
         Self {
             hash: 16,
+            multi_pv: 1,
             log_file: None,
+            pruning: Pruning::default(),
         }
     }
 }
@@ -65,22 +96,86 @@ impl Default for Options {
 pub struct UCIDriver {
     debug: bool,
     board: BitBoardState,
-    search_driver: SearchDriver,
+    history: GameHistory,
+    search_driver: Arc<Mutex<SearchDriver>>,
+    search_signals: Arc<SearchSignals>,
+    search_thread: Option<JoinHandle<()>>,
+    output: UnboundedSender<String>,
     options: Options,
 }
 
 impl UCIDriver {
-    pub fn new() -> Self {
+    /// Output produced by a running search, such as `bestmove`, is sent through `output`
+    pub fn new(output: UnboundedSender<String>) -> Self {
+        let search_driver = new_search_driver(&output);
+        let search_signals = search_driver.signals();
+
         Self {
             debug: false,
             board: BitBoardState::new(),
-            search_driver: SearchDriver::new(16),
+            history: GameHistory::new(),
+            search_driver: Arc::new(Mutex::new(search_driver)),
+            search_signals,
+            search_thread: None,
+            output,
             options: Options::new(),
         }
     }
 
+    /// Starts searching the current position on a worker thread
+    fn start_search(&mut self, limits: SearchLimits) {
+        self.stop_search();
+        self.search_signals.reset(limits.ponder);
+
+        let search_driver = Arc::clone(&self.search_driver);
+        let board = self.board.clone();
+        let history = self.history.clone();
+        let output = self.output.clone();
+
+        self.search_thread = Some(thread::spawn(move || {
+            let mut search_driver = search_driver.lock().unwrap_or_else(PoisonError::into_inner);
+            let response = match search_driver.best_move(&board, &history, &limits) {
+                Some(best_move) => match best_move.to_long_algebraic() {
+                    Ok(m) => format!("bestmove {}", m),
+                    Err(e) => format!("Unable to format move: {}", e),
+                },
+                None => String::from("bestmove 0000"),
+            };
+            // The receiver only disappears when the engine is shutting down
+            let _ = output.send(response);
+        }));
+    }
+
+    /// Hands the options that affect searching over to the search driver
+    fn apply_options(&mut self) {
+        let mut search_driver = self
+            .search_driver
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        search_driver.set_hash(self.options.hash);
+        search_driver.set_multi_pv(self.options.multi_pv);
+        search_driver.set_pruning(self.options.pruning);
+    }
+
+    /// Stops a running search and waits until it has reported its best move
+    fn stop_search(&mut self) {
+        if let Some(search_thread) = self.search_thread.take() {
+            self.search_signals.stop();
+            // A search that panicked may have left the driver half updated, so it is replaced
+            if search_thread.join().is_err() {
+                let _ = self.output.send(String::from(
+                    "info string The search failed, the search driver has been reset",
+                ));
+                let search_driver = new_search_driver(&self.output);
+                self.search_signals = search_driver.signals();
+                self.search_driver = Arc::new(Mutex::new(search_driver));
+                self.apply_options();
+            }
+        }
+    }
+
     pub fn parse_command(&mut self, command: &str) -> ResponseType {
-        let command_vec: Vec<&str> = command.trim().split_whitespace().collect();
+        let command_vec: Vec<&str> = command.split_whitespace().collect();
 
         match *command_vec {
             ["uci"] => {
@@ -90,9 +185,27 @@ impl UCIDriver {
                 response.push_str("uciok");
                 ResponseType::Response(response)
             }
-            ["quit"] => ResponseType::Quit,
+            ["quit"] => {
+                self.stop_search();
+                ResponseType::Quit
+            }
             ["isready"] => ResponseType::Response(String::from("readyok")),
-            ["ucinewgame"] => ResponseType::Nothing,
+            ["stop"] => {
+                self.stop_search();
+                ResponseType::Nothing
+            }
+            ["ponderhit"] => {
+                self.search_signals.ponder_hit();
+                ResponseType::Nothing
+            }
+            ["ucinewgame"] => {
+                self.stop_search();
+                self.search_driver
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .clear_hash();
+                ResponseType::Nothing
+            }
             ["debug", "on"] => {
                 self.debug = true;
                 ResponseType::Nothing
@@ -101,43 +214,49 @@ impl UCIDriver {
                 self.debug = false;
                 ResponseType::Nothing
             }
-            ["position", "fen", fen, "moves", ref moves @ ..] => {
-                self.board = match BitBoardState::from_fen(fen) {
-                    Ok(board) => board,
-                    Err(e) => {
-                        return ResponseType::Response(format!(
-                            "Unable to construct board from FEN: {}",
-                            e
-                        ))
-                    }
-                };
-                ResponseType::Response(format!("{:?}\n", moves))
+            ["position", ..] => {
+                self.stop_search();
+                self.parse_setup_command(&command_vec)
+            }
+            ["setoption", ..] => {
+                self.stop_search();
+                let response = self.parse_setup_command(&command_vec);
+                self.apply_options();
+                response
             }
-            ["position", "fen", fen] => {
-                self.board = match BitBoardState::from_fen(fen) {
-                    Ok(board) => board,
+            ["go", "perft", depth] => {
+                self.stop_search();
+                ResponseType::Response(perft_report(&self.board, depth.parse().unwrap()))
+            }
+            ["go", ref args @ ..] => {
+                let limits = match parse_go(&self.board, args) {
+                    Ok(limits) => limits,
                     Err(e) => {
-                        return ResponseType::Response(format!(
-                            "Unable to construct board from FEN: {}",
-                            e
-                        ))
+                        return ResponseType::Response(format!("Unable to parse go command: {}", e))
                     }
                 };
+
+                self.start_search(limits);
                 ResponseType::Nothing
             }
-            ["position", "startpos", "moves", ref moves @ ..] => {
-                self.board = BitBoardState::new();
-                for m in moves {
-                    self.board
-                        .apply_move(&BitBoardMove::from_long_algebraic(m.as_bytes()).unwrap());
-                    self.board.change_side();
+            _ => ResponseType::Response(format!("Unknown command: {}", command)),
+        }
+    }
+
+    // Commands that change the position or the options, these never run during a search
+    fn parse_setup_command(&mut self, command_vec: &[&str]) -> ResponseType {
+        match *command_vec {
+            ["position", ref args @ ..] => match parse_position(args) {
+                Ok((board, history)) => {
+                    self.board = board;
+                    self.history = history;
+                    ResponseType::Nothing
                 }
-                ResponseType::Nothing
-            }
-            ["position", "startpos"] => {
-                self.board = BitBoardState::new();
-                ResponseType::Nothing
-            }
+                // The previous position stays, so a bad FEN can not reach the search
+                Err(e) => {
+                    ResponseType::Response(format!("info string Unable to set up position: {}", e))
+                }
+            },
             ["setoption", "name", option, "value", value] => {
                 self.options.set_option(option, value);
                 ResponseType::Nothing
@@ -154,31 +273,7 @@ impl UCIDriver {
                 self.options.set_option(option, "");
                 ResponseType::Nothing
             }
-            ["go", "perft", depth] => {
-                ResponseType::Response(perft_report(&self.board, depth.parse().unwrap()))
-            }
-            ["go", ..] => {
-                let best_move = self.search_driver.best_move(&self.board, 6);
-                let from = index_to_algebraic(best_move.get_from() as usize);
-                let to = index_to_algebraic(best_move.get_to() as usize);
-                let promotion = match best_move.get_flags() {
-                    KNIGHT_PROMOTION | KNIGHT_PROMOTION_CAPTURE => String::from("n"),
-                    QUEEN_PROMOTION | QUEEN_PROMOTION_CAPTURE => String::from("q"),
-                    BISHOP_PROMOTION | BISHOP_PROMOTION_CAPTURE => String::from("b"),
-                    ROOK_PROMOTION | ROOK_PROMOTION_CAPTURE => String::from("r"),
-                    _ => String::from(" "),
-                };
-                match (from_utf8(&from), from_utf8(&to)) {
-                    (Ok(f), Ok(t)) => {
-                        ResponseType::Response(format!("bestmove {}{}{}", f, t, promotion))
-                    }
-                    (_, _) => ResponseType::Response(format!(
-                        "Unable to construct index {:?} {:?}",
-                        from, to
-                    )),
-                }
-            }
-            _ => ResponseType::Response(format!("Unknown command: {}", command)),
+            _ => ResponseType::Response(format!("Unknown command: {}", command_vec.join(" "))),
         }
     }
 
@@ -189,3 +284,434 @@ impl UCIDriver {
         )
     }
 }
+
+impl Drop for UCIDriver {
+    fn drop(&mut self) {
+        self.stop_search();
+    }
+}
+
+/// Creates a search driver that sends its progress reports to `output` as `info` lines
+fn new_search_driver(output: &UnboundedSender<String>) -> SearchDriver {
+    let mut search_driver = SearchDriver::new(16);
+    search_driver.set_info_callback({
+        let output = output.clone();
+        move |info| {
+            let _ = output.send(format_info(info));
+        }
+    });
+    search_driver
+}
+
+/// Builds the board described by the arguments that follow `position`, playing the move list
+/// on top of the start position or FEN. The positions the moves passed through are returned
+/// as well, so the search can recognize repetitions.
+fn parse_position(args: &[&str]) -> Result<(BitBoardState, GameHistory), String> {
+    let (setup, moves) = match args.iter().position(|&arg| arg == "moves") {
+        Some(index) => (&args[..index], &args[index + 1..]),
+        None => (args, &[][..]),
+    };
+
+    let mut board = match setup {
+        ["startpos"] => BitBoardState::new(),
+        // The FEN arrives as up to six whitespace separated fields
+        ["fen", ref fen @ ..] if !fen.is_empty() && fen.len() <= 6 => {
+            BitBoardState::from_fen_legal(fen.join(" "))
+                .map_err(|e| format!("Unable to construct board from FEN: {}", e))?
+        }
+        _ => return Err(format!("Invalid position: {}", setup.join(" "))),
+    };
+
+    let mut history = GameHistory::new();
+    for m in moves {
+        let m = board.parse_uci_move(m).map_err(|e| e.to_string())?;
+        history.push(&board);
+        board.apply_move(&m);
+        board.change_side();
+    }
+
+    Ok((board, history))
+}
+
+/// Formats a search report as a UCI `info` line
+pub fn format_info(info: &SearchInfo) -> String {
+    let mut line = String::from("info");
+
+    if let Some(depth) = info.depth {
+        line.push_str(&format!(" depth {}", depth));
+    }
+    if let Some(seldepth) = info.seldepth {
+        line.push_str(&format!(" seldepth {}", seldepth));
+    }
+    if let Some(multipv) = info.multipv {
+        line.push_str(&format!(" multipv {}", multipv));
+    }
+    match info.score {
+        Some(Score::Cp(cp)) => line.push_str(&format!(" score cp {}", cp)),
+        Some(Score::Mate(moves)) => line.push_str(&format!(" score mate {}", moves)),
+        None => {}
+    }
+    if let Some(nodes) = info.nodes {
+        line.push_str(&format!(" nodes {}", nodes));
+    }
+    if let Some(nps) = info.nps {
+        line.push_str(&format!(" nps {}", nps));
+    }
+    if let Some(time) = info.time {
+        line.push_str(&format!(" time {}", time));
+    }
+    if let Some(hashfull) = info.hashfull {
+        line.push_str(&format!(" hashfull {}", hashfull));
+    }
+    if let Some(Ok(currmove)) = info.currmove.map(|m| m.to_long_algebraic()) {
+        line.push_str(&format!(" currmove {}", currmove));
+    }
+    if let Some(currmovenumber) = info.currmovenumber {
+        line.push_str(&format!(" currmovenumber {}", currmovenumber));
+    }
+    if !info.pv.is_empty() {
+        line.push_str(" pv");
+        for m in info.pv.iter().filter_map(|m| m.to_long_algebraic().ok()) {
+            line.push(' ');
+            line.push_str(&m);
+        }
+    }
+
+    line
+}
+
+fn parse_value<T: FromStr>(name: &str, value: Option<&&str>) -> Result<T, String> {
+    let value = value.ok_or(format!("Missing value for {}", name))?;
+    value
+        .parse()
+        .map_err(|_| format!("Invalid value for {}: {}", name, value))
+}
+
+/// Parses the arguments that follow `go` into a set of search limits
+/// Moves after `searchmoves` are resolved against the legal moves of `board`, illegal ones are
+/// skipped
+pub fn parse_go(board: &BitBoardState, args: &[&str]) -> Result<SearchLimits, String> {
+    let mut limits = SearchLimits::new();
+    let mut args = args.iter().peekable();
+
+    while let Some(&arg) = args.next() {
+        match arg {
+            "searchmoves" => {
+                while let Some(m) = args.peek() {
+                    if BitBoardMove::from_long_algebraic(m.as_bytes()).is_err() {
+                        break;
+                    }
+                    if let Ok(m) = board.parse_uci_move(m) {
+                        limits.search_moves.push(m);
+                    }
+                    args.next();
+                }
+            }
+            "ponder" => limits.ponder = true,
+            "wtime" => limits.wtime = Some(parse_value(arg, args.next())?),
+            "btime" => limits.btime = Some(parse_value(arg, args.next())?),
+            "winc" => limits.winc = Some(parse_value(arg, args.next())?),
+            "binc" => limits.binc = Some(parse_value(arg, args.next())?),
+            "movestogo" => limits.moves_to_go = Some(parse_value(arg, args.next())?),
+            "depth" => limits.depth = Some(parse_value(arg, args.next())?),
+            "nodes" => limits.nodes = Some(parse_value(arg, args.next())?),
+            "mate" => limits.mate = Some(parse_value(arg, args.next())?),
+            "movetime" => limits.move_time = Some(parse_value(arg, args.next())?),
+            "infinite" => limits.infinite = true,
+            // Unknown tokens are ignored as the UCI specification asks
+            _ => {}
+        }
+    }
+
+    Ok(limits)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::bitboard::BitBoardMove;
+    use crate::bitboard::BitBoardState;
+    use crate::board::Color;
+    use crate::search::{Pruning, Score, SearchInfo};
+    use crate::transposition::MAX_MEGABYTES;
+    use crate::uci::{format_info, parse_go, Options, ResponseType, UCIDriver};
+    use std::sync::Arc;
+    use std::thread;
+    use tokio::sync::mpsc::unbounded_channel;
+
+    #[test]
+    fn test_stop() {
+        let (output, mut search_output) = unbounded_channel();
+        let mut uci_driver = UCIDriver::new(output);
+
+        uci_driver.parse_command("position startpos");
+        uci_driver.parse_command("go infinite");
+        assert!(matches!(
+            uci_driver.parse_command("isready"),
+            ResponseType::Response(response) if response == "readyok"
+        ));
+        uci_driver.parse_command("stop");
+        drop(uci_driver);
+
+        let mut responses = Vec::new();
+        while let Some(response) = search_output.blocking_recv() {
+            responses.push(response);
+        }
+        let (bestmove, infos) = responses.split_last().unwrap();
+        assert!(bestmove.starts_with("bestmove "));
+        assert!(infos.iter().all(|info| info.starts_with("info ")));
+    }
+
+    #[test]
+    fn test_multi_pv() {
+        let (output, mut search_output) = unbounded_channel();
+        let mut uci_driver = UCIDriver::new(output);
+
+        uci_driver.parse_command("setoption name MultiPV value 2");
+        uci_driver.parse_command("position startpos");
+        uci_driver.parse_command("go depth 2");
+
+        let mut responses = Vec::new();
+        while let Some(response) = search_output.blocking_recv() {
+            responses.push(response);
+            if responses.last().unwrap().starts_with("bestmove ") {
+                break;
+            }
+        }
+        assert!(responses
+            .iter()
+            .any(|r| r.contains("depth 2 ") && r.contains(" multipv 1 ")));
+        assert!(responses
+            .iter()
+            .any(|r| r.contains("depth 2 ") && r.contains(" multipv 2 ")));
+        assert!(responses.last().unwrap().starts_with("bestmove "));
+    }
+
+    #[test]
+    fn test_failed_search() {
+        let (output, mut search_output) = unbounded_channel();
+        let mut uci_driver = UCIDriver::new(output);
+
+        // A search that panics while holding the driver poisons its lock
+        let search_driver = Arc::clone(&uci_driver.search_driver);
+        uci_driver.search_thread = Some(thread::spawn(move || {
+            let _search_driver = search_driver.lock().unwrap();
+            panic!("search failed");
+        }));
+
+        uci_driver.parse_command("ucinewgame");
+        assert_eq!(
+            search_output.blocking_recv().unwrap(),
+            "info string The search failed, the search driver has been reset"
+        );
+        assert!(!uci_driver.search_driver.is_poisoned());
+
+        uci_driver.parse_command("setoption name Hash value 1");
+        uci_driver.parse_command("position startpos");
+        uci_driver.parse_command("go depth 1");
+        while let Some(response) = search_output.blocking_recv() {
+            if response.starts_with("bestmove ") {
+                break;
+            }
+        }
+
+        // A poisoned lock is recovered instead of taking the engine down
+        let search_driver = Arc::clone(&uci_driver.search_driver);
+        let _ = thread::spawn(move || {
+            let _search_driver = search_driver.lock().unwrap();
+            panic!("poisoned");
+        })
+        .join();
+        uci_driver.parse_command("ucinewgame");
+        uci_driver.parse_command("setoption name MultiPV value 2");
+    }
+
+    #[test]
+    fn test_hash_option() {
+        let mut options = Options::new();
+        assert!(options.get_options().contains(&format!(
+            "option name Hash type spin default 16 min 1 max {}\n",
+            MAX_MEGABYTES
+        )));
+
+        options.set_option("Hash", "64");
+        assert_eq!(options.hash, 64);
+        options.set_option("Hash", "33554432");
+        assert_eq!(options.hash, MAX_MEGABYTES);
+        options.set_option("Hash", "0");
+        assert_eq!(options.hash, 1);
+    }
+
+    #[test]
+    fn test_pruning_options() {
+        let (output, _search_output) = unbounded_channel();
+        let mut uci_driver = UCIDriver::new(output);
+
+        uci_driver.parse_command("setoption name NullMove value false");
+        uci_driver.parse_command("setoption name LateMovePruning value false");
+        uci_driver.parse_command("setoption name Futility value maybe");
+        assert_eq!(
+            uci_driver.options.pruning,
+            Pruning {
+                null_move: false,
+                late_move_pruning: false,
+                ..Pruning::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_position() {
+        let (output, _search_output) = unbounded_channel();
+        let mut uci_driver = UCIDriver::new(output);
+
+        assert!(matches!(
+            uci_driver.parse_command(
+                "position fen rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1 \
+                 moves e7e5 g1f3"
+            ),
+            ResponseType::Nothing
+        ));
+        let expected =
+            BitBoardState::from_fen("rnbqkbnr/pppp1ppp/8/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R b").unwrap();
+        assert_eq!(uci_driver.board.bitboard.0, expected.bitboard.0);
+        assert_eq!(uci_driver.board.active_color, Color::Black);
+
+        // A position with an illegal move is rejected as a whole
+        assert!(matches!(
+            uci_driver.parse_command("position startpos moves e2e4 e7e5 e1e3"),
+            ResponseType::Response(response) if response.contains("e1e3")
+        ));
+        assert_eq!(uci_driver.board.bitboard.0, expected.bitboard.0);
+
+        // Positions that can not occur are rejected as well, the clocks may be left out
+        for fen in &[
+            "8/8/8/8/8/8/8/8 w - - 0 1",
+            "4k3/8/8/8/8/8/8/3KK3 w - - 0 1",
+            "4k3/8/8/8/8/8/8/4K3 w K - 0 1",
+            "4k3/8/8/8/8/8/4P3/4K3 w - e3 0 1",
+            "4k3/8/8/8/8/8/8/4K3",
+        ] {
+            assert!(
+                matches!(
+                    uci_driver.parse_command(&format!("position fen {}", fen)),
+                    ResponseType::Response(response) if response.starts_with("info string")
+                ),
+                "{}",
+                fen
+            );
+            assert_eq!(uci_driver.board.bitboard.0, expected.bitboard.0);
+        }
+        assert!(matches!(
+            uci_driver.parse_command("position fen 4k3/8/8/8/8/8/8/R3K3 w Q -"),
+            ResponseType::Nothing
+        ));
+        assert_eq!(uci_driver.board.to_fen(), "4k3/8/8/8/8/8/8/R3K3 w Q - 0 1");
+
+        uci_driver.parse_command("position startpos moves e2e4");
+        assert_eq!(uci_driver.board.active_color, Color::Black);
+
+        uci_driver.parse_command("position startpos moves g1f3 g8f6 f3g1 f6g8");
+        assert_eq!(uci_driver.history.len(), 4);
+        assert!(uci_driver.history.is_repetition(&uci_driver.board));
+    }
+
+    #[test]
+    fn test_format_info() {
+        let info = SearchInfo {
+            depth: Some(4),
+            seldepth: Some(6),
+            score: Some(Score::Cp(-25)),
+            nodes: Some(12_000),
+            nps: Some(240_000),
+            time: Some(50),
+            hashfull: Some(3),
+            pv: vec![
+                BitBoardMove::from_long_algebraic(b"e2e4").unwrap(),
+                BitBoardMove::from_long_algebraic(b"e7e5").unwrap(),
+            ],
+            ..SearchInfo::default()
+        };
+        assert_eq!(
+            format_info(&info),
+            "info depth 4 seldepth 6 score cp -25 nodes 12000 nps 240000 time 50 hashfull 3 \
+             pv e2e4 e7e5"
+        );
+
+        let info = SearchInfo {
+            score: Some(Score::Mate(-2)),
+            currmove: Some(BitBoardMove::from_long_algebraic(b"g1f3").unwrap()),
+            currmovenumber: Some(7),
+            ..SearchInfo::default()
+        };
+        assert_eq!(
+            format_info(&info),
+            "info score mate -2 currmove g1f3 currmovenumber 7"
+        );
+    }
+
+    #[test]
+    fn test_parse_go() {
+        let board = BitBoardState::new();
+        let limits = parse_go(
+            &board,
+            &[
+                "wtime",
+                "300000",
+                "btime",
+                "295000",
+                "winc",
+                "2000",
+                "binc",
+                "2000",
+                "movestogo",
+                "40",
+            ],
+        )
+        .unwrap();
+        assert_eq!(limits.wtime, Some(300_000));
+        assert_eq!(limits.btime, Some(295_000));
+        assert_eq!(limits.winc, Some(2_000));
+        assert_eq!(limits.binc, Some(2_000));
+        assert_eq!(limits.moves_to_go, Some(40));
+        assert!(!limits.infinite);
+
+        let limits = parse_go(
+            &board,
+            &[
+                "searchmoves",
+                "e2e4",
+                "e2e5",
+                "d2d4",
+                "depth",
+                "5",
+                "nodes",
+                "100",
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            limits.search_moves,
+            vec![
+                BitBoardMove::from_long_algebraic(b"e2e4").unwrap(),
+                BitBoardMove::from_long_algebraic(b"d2d4").unwrap()
+            ]
+        );
+        assert_eq!(limits.depth, Some(5));
+        assert_eq!(limits.nodes, Some(100));
+
+        let limits = parse_go(&board, &["infinite"]).unwrap();
+        assert!(limits.infinite);
+
+        assert!(parse_go(&board, &["movetime"]).is_err());
+        assert!(parse_go(&board, &["movetime", "soon"]).is_err());
+
+        // Only the promotion that was asked for is searched
+        let board = BitBoardState::from_fen("8/4P3/8/8/8/k7/8/K7 w - - 0 1").unwrap();
+        let limits = parse_go(&board, &["searchmoves", "e7e8q"]).unwrap();
+        assert_eq!(limits.search_moves.len(), 1);
+        assert_eq!(
+            limits.search_moves[0].to_u16(),
+            board.parse_uci_move("e7e8q").unwrap().to_u16()
+        );
+    }
+}
@@ -6,28 +6,28 @@ impl Rng {
     pub const fn new(seed: u64) -> Self {
         let mut s = seed;
 
-        s += 0x9E3779B97f4A7C15;
+        s = s.wrapping_add(0x9E3779B97F4A7C15);
         let mut result = s;
-        result = (result ^ (result >> 30)) * 0xBF58476D1CE4E5B9;
-        result = (result ^ (result >> 27)) * 0x94D049BB133111EB;
+        result = (result ^ (result >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        result = (result ^ (result >> 27)).wrapping_mul(0x94D049BB133111EB);
         let a = result ^ (result >> 31);
 
-        s += 0x9E3779B97f4A7C15;
+        s = s.wrapping_add(0x9E3779B97F4A7C15);
         let mut result = s;
-        result = (result ^ (result >> 30)) * 0xBF58476D1CE4E5B9;
-        result = (result ^ (result >> 27)) * 0x94D049BB133111EB;
+        result = (result ^ (result >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        result = (result ^ (result >> 27)).wrapping_mul(0x94D049BB133111EB);
         let b = result ^ (result >> 31);
 
-        s += 0x9E3779B97f4A7C15;
+        s = s.wrapping_add(0x9E3779B97F4A7C15);
         let mut result = s;
-        result = (result ^ (result >> 30)) * 0xBF58476D1CE4E5B9;
-        result = (result ^ (result >> 27)) * 0x94D049BB133111EB;
+        result = (result ^ (result >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        result = (result ^ (result >> 27)).wrapping_mul(0x94D049BB133111EB);
         let c = result ^ (result >> 31);
 
-        s += 0x9E3779B97f4A7C15;
+        s = s.wrapping_add(0x9E3779B97F4A7C15);
         let mut result = s;
-        result = (result ^ (result >> 30)) * 0xBF58476D1CE4E5B9;
-        result = (result ^ (result >> 27)) * 0x94D049BB133111EB;
+        result = (result ^ (result >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        result = (result ^ (result >> 27)).wrapping_mul(0x94D049BB133111EB);
         let d = result ^ (result >> 31);
 
         Rng { s: [a, b, c, d] }
@@ -38,7 +38,7 @@ impl Rng {
     }
 
     pub fn rand_u64(&mut self) -> u64 {
-        let result = (self.s[1] * 5).rotate_left(7) * 9;
+        let result = self.s[1].wrapping_mul(5).rotate_left(7).wrapping_mul(9);
         let t = self.s[1] << 17;
 
         self.s[2] ^= self.s[0];
@@ -49,11 +49,11 @@ impl Rng {
         self.s[2] ^= t;
         self.s[3] = self.s[3].rotate_left(45);
 
-        return result;
+        result
     }
 
     pub const fn const_rand_u64(&self) -> (u64, Rng) {
-        let result = (self.s[1] * 5).rotate_left(7) * 9;
+        let result = self.s[1].wrapping_mul(5).rotate_left(7).wrapping_mul(9);
         let t = self.s[1] << 17;
 
         let mut s0 = self.s[0];
@@ -69,12 +69,12 @@ impl Rng {
         s2 ^= t;
         s3 = s3.rotate_left(45);
 
-        return (
+        (
             result,
             Rng {
                 s: [s0, s1, s2, s3],
             },
-        );
+        )
     }
 
     pub fn rand_f64(&mut self, low: f64, high: f64) -> f64 {
@@ -0,0 +1,127 @@
+use crate::board::{Color, Piece};
+use crate::util::Rng;
+
+// Keys are laid out like the 781 keys of the Polyglot opening book format
+const PIECE_KEYS: usize = 0;
+const CASTLING_KEYS: usize = 768;
+const EN_PASSANT_KEYS: usize = 772;
+const TURN_KEY: usize = 780;
+pub const KEY_COUNT: usize = 781;
+
+const SEED: u64 = 0x005a_6f62_7269_7374;
+
+/// The keys `BitBoardState` keeps its hash up to date with. They come from a fixed seed, so
+/// hashes are the same in every run.
+pub static ZOBRIST_KEYS: ZobristKeys = ZobristKeys::from_seed(SEED);
+
+/// Random keys for every piece on every square, the castling rights, the en passant file and
+/// the side to move.
+///
+/// The layout and the rules for when a key is used follow Polyglot: castling rights are keyed
+/// one by one, the en passant file only counts when a pawn of the side to move stands next to
+/// the pawn that just moved two squares, and the turn key is used when white is to move.
+/// Loading the Random64 table of the Polyglot format with `from_keys` therefore gives hashes
+/// that match Polyglot opening books.
+#[derive(Clone, Debug)]
+pub struct ZobristKeys {
+    keys: [u64; KEY_COUNT],
+}
+
+impl ZobristKeys {
+    pub const fn from_seed(seed: u64) -> Self {
+        let mut keys = [0; KEY_COUNT];
+        let mut rng = Rng::new(seed);
+        let mut i = 0;
+        while i < KEY_COUNT {
+            let (key, next) = rng.const_rand_u64();
+            keys[i] = key;
+            rng = next;
+            i += 1;
+        }
+        Self { keys }
+    }
+
+    pub const fn from_keys(keys: [u64; KEY_COUNT]) -> Self {
+        Self { keys }
+    }
+
+    pub const fn piece(&self, color: Color, piece: Piece, square: usize) -> u64 {
+        // Polyglot orders pieces from pawn to king, black before white
+        let kind = match piece {
+            Piece::Pawn => 0,
+            Piece::Knight => 1,
+            Piece::Bishop => 2,
+            Piece::Rook => 3,
+            Piece::Queen => 4,
+            Piece::King => 5,
+        };
+        let color = match color {
+            Color::Black => 0,
+            Color::White => 1,
+        };
+        self.keys[PIECE_KEYS + 64 * (2 * kind + color) + square]
+    }
+
+    /// Key of the castling rights, from white king side to black queen side
+    pub const fn castling(
+        &self,
+        white_king: bool,
+        white_queen: bool,
+        black_king: bool,
+        black_queen: bool,
+    ) -> u64 {
+        let mut key = 0;
+        if white_king {
+            key ^= self.keys[CASTLING_KEYS];
+        }
+        if white_queen {
+            key ^= self.keys[CASTLING_KEYS + 1];
+        }
+        if black_king {
+            key ^= self.keys[CASTLING_KEYS + 2];
+        }
+        if black_queen {
+            key ^= self.keys[CASTLING_KEYS + 3];
+        }
+        key
+    }
+
+    pub const fn en_passant(&self, file: usize) -> u64 {
+        self.keys[EN_PASSANT_KEYS + file]
+    }
+
+    pub const fn turn(&self) -> u64 {
+        self.keys[TURN_KEY]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::board::{Color, Piece};
+    use crate::zobrist::{ZobristKeys, KEY_COUNT, SEED, ZOBRIST_KEYS};
+
+    #[test]
+    fn test_keys() {
+        // Reproducible and all different
+        let keys = ZobristKeys::from_seed(SEED);
+        assert_eq!(keys.keys[..], ZOBRIST_KEYS.keys[..]);
+
+        let mut sorted = keys.keys.to_vec();
+        sorted.sort_unstable();
+        sorted.dedup();
+        assert_eq!(sorted.len(), KEY_COUNT);
+
+        // Polyglot layout
+        let mut keys = [0; KEY_COUNT];
+        for (i, key) in keys.iter_mut().enumerate() {
+            *key = i as u64;
+        }
+        let keys = ZobristKeys::from_keys(keys);
+        assert_eq!(keys.piece(Color::Black, Piece::Pawn, 0), 0);
+        assert_eq!(keys.piece(Color::White, Piece::Pawn, 8), 72);
+        assert_eq!(keys.piece(Color::White, Piece::King, 63), 767);
+        assert_eq!(keys.castling(false, true, false, false), 769);
+        assert_eq!(keys.en_passant(7), 779);
+        assert_eq!(keys.turn(), 780);
+    }
+}